@@ -1,4 +1,9 @@
-const FEET_TO_METERS: f64 = 0.3048;
+use std::{
+    cmp::Ordering,
+    ops::{Add, Neg, Sub},
+};
+
+pub(crate) const FEET_TO_METERS: f64 = 0.3048;
 
 /// East North Elevation coordinates
 /// Always stored in meters
@@ -29,16 +34,83 @@ impl EastNorthElevation {
     }
 }
 
+impl Add for EastNorthElevation {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::from_meters(
+            self.easting + other.easting,
+            self.northing + other.northing,
+            self.up + other.up,
+        )
+    }
+}
+
+impl Sub for EastNorthElevation {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Neg for EastNorthElevation {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_meters(-self.easting, -self.northing, -self.up)
+    }
+}
+
+/// Half of the world a UTM location is in
+/// Southern hemisphere northings are measured from a false origin 10,000 km south of the equator.
+/// Compass writes southern hemisphere zones as negative numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hemisphere {
+    #[default]
+    North,
+    South,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct UtmLocation {
     pub east_north_elevation: EastNorthElevation,
     pub zone: u8,
+    pub hemisphere: Hemisphere,
     pub convergence_angle: f64,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Date {
     pub month: u8,
     pub day: u8,
     pub year: u16,
 }
+
+impl Date {
+    /// The year with two digit years taken as 19xx, the way Compass reads them
+    /// Older survey data often records `79` for 1979.
+    #[must_use]
+    pub fn full_year(self) -> u16 {
+        if self.year < 100 {
+            self.year + 1900
+        } else {
+            self.year
+        }
+    }
+}
+
+/// Dates are ordered by year, month and day as stored
+/// Two digit years compare as written, so `79` sorts before `1978`.
+/// Normalise them with [`Date::full_year`] before comparing dates that may mix the two.
+impl Ord for Date {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.year, self.month, self.day).cmp(&(other.year, other.month, other.day))
+    }
+}
+
+impl PartialOrd for Date {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -8,6 +8,8 @@ pub enum Error {
     ProjectFileNotFound(PathBuf),
     #[error("Error reading file: {0}")]
     CouldntReadFile(#[from] std::io::Error),
+    #[error("Error writing file: {0}")]
+    CouldntWriteFile(std::io::Error),
     #[error("Error parsing project file: {0}")]
     CouldntParseProject(String),
     #[error("Survey file not found: {0}")]
@@ -16,4 +18,10 @@ pub enum Error {
     CouldntParseSurvey(String),
     #[error("Station not found: {0}")]
     StationNotFound(String),
+    #[error("Invalid base location: {0}")]
+    InvalidBaseLocation(String),
+    #[error("Invalid UTM zone: {0}")]
+    InvalidUtmZone(String),
+    #[error("Error parsing geomagnetic model: {0}")]
+    CouldntParseGeomagneticModel(String),
 }
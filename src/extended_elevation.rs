@@ -0,0 +1,372 @@
+//! Extended elevation
+//!
+//! Unrolls the centerline into a developed profile: each shot is laid out horizontally by its horizontal length,
+//! to the right or left, keeping its true change in elevation.
+//! Drawings use it alongside the plan view to show the vertical shape of the cave without passages overlapping.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    project::Loaded,
+    reduction::{azimuth_difference, project_legs, survey_legs, Corrections, Leg},
+    EastNorthElevation, Error, Project, Station, Survey, SurveyFile,
+};
+
+/// Branches at a junction turning back by more than this many degrees from the passage they leave
+/// are laid out in the opposite direction
+const TURN_BACK_ANGLE: f64 = 90.0;
+
+/// How to unroll the centerline
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtendedElevationOptions {
+    /// Station placed at distance zero, where unrolling begins
+    /// `None` starts from the first fixed station of a project, or the first station in the survey data
+    pub start: Option<String>,
+    /// Stations where the profile reverses direction, for the passage beyond them
+    pub flips: HashSet<String>,
+}
+
+/// A station's place on the extended elevation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProfilePoint {
+    /// Horizontal position along the profile in meters, increasing to the right
+    pub distance: f64,
+    /// Elevation in meters, as in the station coordinates
+    pub elevation: f64,
+}
+
+/// The centerline unrolled into an extended elevation
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtendedElevation {
+    /// Where each station falls on the profile
+    pub stations: HashMap<String, ProfilePoint>,
+    /// Shots to draw between stations, in the order they were unrolled
+    /// Shots closing loops are left out, since their stations were already placed along another path.
+    pub legs: Vec<(String, String)>,
+}
+
+/// Place every station connected by the legs on the profile
+fn unroll(
+    legs: &[Leg<'_>],
+    coordinates: &HashMap<String, EastNorthElevation>,
+    start: &str,
+    flips: &HashSet<String>,
+) -> ExtendedElevation {
+    let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut stations = vec![start];
+    for leg in legs {
+        for (station, neighbour) in [(leg.from, leg.to), (leg.to, leg.from)] {
+            neighbours
+                .entry(station)
+                .or_insert_with(|| {
+                    stations.push(station);
+                    Vec::new()
+                })
+                .push(neighbour);
+        }
+    }
+
+    let mut profile = ExtendedElevation {
+        stations: HashMap::new(),
+        legs: Vec::new(),
+    };
+    let mut furthest = 0.0_f64;
+    // Stations waiting to have their branches unrolled, with the station they were reached from,
+    // the direction they were reached in and the azimuth of the shot that reached them
+    let mut stack: Vec<(&str, Option<&str>, f64, Option<f64>)> = Vec::new();
+    let mut next_start = stations.iter();
+    loop {
+        while let Some((station, reached_from, reached_direction, heading)) = stack.pop() {
+            if let Some(reached_from) = reached_from {
+                profile
+                    .legs
+                    .push((reached_from.to_string(), station.to_string()));
+            }
+            let direction = if flips.contains(station) {
+                -reached_direction
+            } else {
+                reached_direction
+            };
+            let here = coordinates[station];
+            let distance = profile.stations[station].distance;
+            let mut branches: Vec<(&str, f64, f64)> = Vec::new();
+            for &neighbour in neighbours.get(station).into_iter().flatten() {
+                if profile.stations.contains_key(neighbour)
+                    || branches.iter().any(|(branch, ..)| *branch == neighbour)
+                {
+                    continue;
+                }
+                let Some(&there) = coordinates.get(neighbour) else {
+                    continue;
+                };
+                let vector = there - here;
+                branches.push((
+                    neighbour,
+                    vector.easting.hypot(vector.northing),
+                    vector.easting.atan2(vector.northing).to_degrees(),
+                ));
+            }
+            let junction = branches.len() > 1;
+            // A station reached without a shot, like the start, lays its first branch out in its direction
+            // and measures the other branches against that one
+            let heading = heading.or_else(|| branches.first().map(|&(.., azimuth)| azimuth));
+            if let (true, Some(heading)) = (junction, heading) {
+                // The branch carrying on most directly is unrolled first
+                branches.sort_by(|(_, _, a), (_, _, b)| {
+                    azimuth_difference(heading, *a)
+                        .abs()
+                        .total_cmp(&azimuth_difference(heading, *b).abs())
+                });
+            }
+            for &(neighbour, horizontal, azimuth) in branches.iter().rev() {
+                let branch_direction = match heading {
+                    Some(heading)
+                        if junction
+                            && azimuth_difference(heading, azimuth).abs() > TURN_BACK_ANGLE =>
+                    {
+                        -direction
+                    }
+                    _ => direction,
+                };
+                let point = ProfilePoint {
+                    distance: distance + branch_direction * horizontal,
+                    elevation: coordinates[neighbour].up,
+                };
+                furthest = furthest.max(point.distance);
+                profile.stations.insert(neighbour.to_string(), point);
+                stack.push((neighbour, Some(station), branch_direction, Some(azimuth)));
+            }
+        }
+        // Parts of the cave which aren't connected are laid out one after another
+        let Some(&station) = next_start.find(|station| {
+            !profile.stations.contains_key(**station) && coordinates.contains_key(**station)
+        }) else {
+            break;
+        };
+        profile.stations.insert(
+            station.to_string(),
+            ProfilePoint {
+                distance: furthest,
+                elevation: coordinates[station].up,
+            },
+        );
+        stack.push((station, None, 1.0, None));
+    }
+    profile
+}
+
+fn check_stations(
+    options: &ExtendedElevationOptions,
+    coordinates: &HashMap<String, EastNorthElevation>,
+) -> Result<(), Error> {
+    for station in options.start.iter().chain(&options.flips) {
+        if !coordinates.contains_key(station) {
+            return Err(Error::StationNotFound(station.clone()));
+        }
+    }
+    Ok(())
+}
+
+impl Survey {
+    /// Unroll the survey into an extended elevation, from [`Survey::station_coordinates`]
+    /// At a junction the branch carrying on most directly keeps the current direction,
+    /// other branches keep it too unless they turn back by more than 90 degrees.
+    /// The first branch from the start is laid out to the right,
+    /// and branches from the start turning back by more than 90 degrees from it to the left.
+    /// # Errors
+    /// - [`Error::StationNotFound`] If the start or a flip station isn't in the survey
+    pub fn extended_elevation(
+        &self,
+        options: &ExtendedElevationOptions,
+    ) -> Result<ExtendedElevation, Error> {
+        let coordinates = self.station_coordinates();
+        check_stations(options, &coordinates)?;
+        let legs = survey_legs(self, Corrections::SURVEY);
+        let Some(start) = options
+            .start
+            .as_deref()
+            .or_else(|| legs.first().map(|leg| leg.from))
+        else {
+            return Ok(ExtendedElevation {
+                stations: HashMap::new(),
+                legs: Vec::new(),
+            });
+        };
+        Ok(unroll(&legs, &coordinates, start, &options.flips))
+    }
+}
+
+impl Project<Loaded> {
+    /// Unroll the whole project into an extended elevation, from [`Project::station_coordinates`]
+    /// See [`Survey::extended_elevation`] for how junctions are laid out.
+    /// # Errors
+    /// - [`Error::StationNotFound`] If the start or a flip station isn't in the project
+    pub fn extended_elevation(
+        &self,
+        options: &ExtendedElevationOptions,
+    ) -> Result<ExtendedElevation, Error> {
+        let coordinates = self.station_coordinates();
+        check_stations(options, &coordinates)?;
+        let legs = project_legs(self);
+        let first_fix = self
+            .survey_files
+            .iter()
+            .flat_map(SurveyFile::fixed_stations)
+            .map(Station::name)
+            .find(|station| coordinates.contains_key(*station));
+        let Some(start) = options
+            .start
+            .as_deref()
+            .or(first_fix)
+            .or_else(|| legs.first().map(|leg| leg.from))
+        else {
+            return Ok(ExtendedElevation {
+                stations: HashMap::new(),
+                legs: Vec::new(),
+            });
+        };
+        Ok(unroll(&legs, &coordinates, start, &options.flips))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{common_types::FEET_TO_METERS, test_utils::survey, Shot};
+
+    fn assert_point(point: ProfilePoint, distance: f64, elevation: f64) {
+        assert_float_eq!(point.distance, distance * FEET_TO_METERS, abs <= 1e-9);
+        assert_float_eq!(point.elevation, elevation * FEET_TO_METERS, abs <= 1e-9);
+    }
+
+    #[test]
+    fn unroll_a_winding_passage() {
+        // A passage winding east, north east and west, which the plan view would draw on top of itself
+        let survey = survey(
+            "E",
+            vec![
+                Shot::new("E1", "E2", 10.0, 90.0, 0.0),
+                Shot::new("E2", "E3", 10.0, 30.0, -30.0),
+                Shot::new("E3", "E4", 10.0, 270.0, 0.0),
+            ],
+        );
+        let profile = survey
+            .extended_elevation(&ExtendedElevationOptions::default())
+            .unwrap();
+        assert_point(profile.stations["E1"], 0.0, 0.0);
+        assert_point(profile.stations["E2"], 10.0, 0.0);
+        assert_point(
+            profile.stations["E3"],
+            10.0 + 10.0 * 3f64.sqrt() / 2.0,
+            -5.0,
+        );
+        assert_point(
+            profile.stations["E4"],
+            20.0 + 10.0 * 3f64.sqrt() / 2.0,
+            -5.0,
+        );
+        assert_eq!(
+            profile.legs,
+            vec![
+                ("E1".to_string(), "E2".to_string()),
+                ("E2".to_string(), "E3".to_string()),
+                ("E3".to_string(), "E4".to_string()),
+            ]
+        );
+
+        let options = ExtendedElevationOptions {
+            start: Some("E2".to_string()),
+            flips: HashSet::from(["E3".to_string()]),
+        };
+        let profile = survey.extended_elevation(&options).unwrap();
+        // Starting mid-passage, E1 is the first branch and goes right,
+        // E3 turns back from it by 120 degrees and goes left
+        assert_point(profile.stations["E2"], 0.0, 0.0);
+        assert_point(profile.stations["E1"], 10.0, 0.0);
+        let e3 = -10.0 * 3f64.sqrt() / 2.0;
+        assert_point(profile.stations["E3"], e3, -5.0);
+        assert_point(profile.stations["E4"], e3 + 10.0, -5.0);
+
+        let missing = ExtendedElevationOptions {
+            start: None,
+            flips: HashSet::from(["E9".to_string()]),
+        };
+        assert!(matches!(
+            survey.extended_elevation(&missing),
+            Err(Error::StationNotFound(station)) if station == "E9"
+        ));
+    }
+
+    #[test]
+    fn junctions_and_loops() {
+        let survey = survey(
+            "E",
+            vec![
+                Shot::new("J1", "J2", 10.0, 90.0, 0.0),
+                // Carries straight on
+                Shot::new("J2", "J3", 10.0, 100.0, 0.0),
+                // Turns back toward the entrance
+                Shot::new("J2", "J4", 10.0, 250.0, 0.0),
+                // Closes a loop back to J3
+                Shot::new("J4", "J3", 6.0, 90.0, 0.0),
+                // Not connected to the rest
+                Shot::new("K1", "K2", 10.0, 0.0, 0.0),
+            ],
+        );
+        let profile = survey
+            .extended_elevation(&ExtendedElevationOptions::default())
+            .unwrap();
+        let j3 = profile.stations["J3"].distance;
+        let j4 = profile.stations["J4"].distance;
+        assert!(j3 > 10.0 * FEET_TO_METERS);
+        assert!(j4 < 10.0 * FEET_TO_METERS);
+        // The loop closing shot isn't drawn, and J1 stays where it started
+        assert_point(profile.stations["J1"], 0.0, 0.0);
+        assert!(!profile.legs.contains(&("J4".to_string(), "J3".to_string())));
+        assert!(!profile.legs.contains(&("J3".to_string(), "J4".to_string())));
+        assert_eq!(profile.legs.len(), 4);
+        // The separate passage starts where the furthest station ended
+        assert_float_eq!(profile.stations["K1"].distance, j3, abs <= 1e-9);
+        assert_float_eq!(
+            profile.stations["K2"].distance,
+            j3 + 10.0 * FEET_TO_METERS,
+            abs <= 1e-9
+        );
+    }
+
+    #[test]
+    fn project_profile() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        let coordinates = project.station_coordinates();
+        let profile = project
+            .extended_elevation(&ExtendedElevationOptions::default())
+            .unwrap();
+        assert_eq!(profile.stations.len(), coordinates.len());
+        assert_float_eq!(profile.stations["A1"].distance, 0.0, abs <= f64::EPSILON);
+        for (station, point) in &profile.stations {
+            assert_float_eq!(
+                point.elevation,
+                coordinates[station].up,
+                abs <= f64::EPSILON
+            );
+        }
+        // Every drawn shot keeps its horizontal length
+        for (from, to) in &profile.legs {
+            let vector = coordinates[to] - coordinates[from];
+            assert_float_eq!(
+                (profile.stations[to].distance - profile.stations[from].distance).abs(),
+                vector.easting.hypot(vector.northing),
+                abs <= 1e-9
+            );
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Shifts between datums
+//!
+//! Latitudes and longitudes are carried through earth centered cartesian coordinates,
+//! where each datum is related to WGS84 by a seven parameter Helmert transformation.
+//! Most datums only have the three parameter mean translations published in NIMA TR8350.2,
+//! which are good to 5 to 25 meters depending on the datum.
+
+use std::f64::consts::PI;
+
+use crate::Datum;
+
+use super::Ellipsoid;
+
+const ARC_SECONDS_TO_RADIANS: f64 = PI / (180.0 * 3600.0);
+
+/// Seven parameter Helmert transformation from a datum to WGS84,
+/// using the position vector rotation convention
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Helmert {
+    /// Translation of the origin along x, y and z in meters
+    pub(crate) translation: [f64; 3],
+    /// Rotation about the x, y and z axes in arc seconds
+    pub(crate) rotation: [f64; 3],
+    /// Scale change in parts per million
+    pub(crate) scale: f64,
+}
+
+impl Helmert {
+    /// A shift of the origin alone, as published for most datums
+    const fn translation(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            translation: [x, y, z],
+            rotation: [0.0, 0.0, 0.0],
+            scale: 0.0,
+        }
+    }
+
+    /// The transformation back the other way
+    /// Exact for translations, and to well under a millimeter for the small rotations and scales of datum shifts
+    fn inverse(self) -> Self {
+        let [x, y, z] = self.translation;
+        let [rx, ry, rz] = self.rotation;
+        Self {
+            translation: [-x, -y, -z],
+            rotation: [-rx, -ry, -rz],
+            scale: -self.scale,
+        }
+    }
+
+    fn apply(self, [x, y, z]: [f64; 3]) -> [f64; 3] {
+        let [tx, ty, tz] = self.translation;
+        let [rx, ry, rz] = self.rotation.map(|angle| angle * ARC_SECONDS_TO_RADIANS);
+        let s = 1.0 + self.scale * 1e-6;
+        [
+            tx + s * x - rz * y + ry * z,
+            ty + rz * x + s * y - rx * z,
+            tz - ry * x + rx * y + s * z,
+        ]
+    }
+}
+
+impl Datum {
+    /// Transformation from the datum to WGS84
+    /// Translations are the NIMA TR8350.2 mean values for the region the datum covers.
+    /// Ordnance Survey 1936 uses the Ordnance Survey's own seven parameter set,
+    /// and WGS 1972 the seven parameter set from TR8350.2.
+    pub(crate) fn to_wgs84(self) -> Helmert {
+        match self {
+            Self::Adindan => Helmert::translation(-166.0, -15.0, 204.0),
+            Self::Arc1950 => Helmert::translation(-143.0, -90.0, -294.0),
+            Self::Arc1960 => Helmert::translation(-160.0, -6.0, -302.0),
+            Self::Australian1966 => Helmert::translation(-133.0, -48.0, 148.0),
+            Self::Australian1984 => Helmert::translation(-134.0, -48.0, 149.0),
+            Self::CampAreaAstro => Helmert::translation(-104.0, -129.0, 239.0),
+            Self::Cape => Helmert::translation(-136.0, -108.0, -292.0),
+            Self::European1950 => Helmert::translation(-87.0, -98.0, -121.0),
+            Self::European1979 => Helmert::translation(-86.0, -98.0, -119.0),
+            Self::Geodetic1949 => Helmert::translation(84.0, -22.0, 209.0),
+            Self::HongKong1963 => Helmert::translation(-156.0, -271.0, -189.0),
+            Self::HuTzuShan => Helmert::translation(-637.0, -549.0, -203.0),
+            Self::Indian => Helmert::translation(282.0, 726.0, 254.0),
+            Self::NorthAmerican1927 => Helmert::translation(-8.0, 160.0, 176.0),
+            Self::NorthAmerican1983 | Self::Wgs1984 => Helmert::translation(0.0, 0.0, 0.0),
+            Self::Oman => Helmert::translation(-346.0, -1.0, 224.0),
+            Self::OrdinanceSurvey1936 => Helmert {
+                translation: [446.448, -125.157, 542.060],
+                rotation: [0.1502, 0.2470, 0.8421],
+                scale: -20.4894,
+            },
+            Self::Pulkovo1942 => Helmert::translation(28.0, -130.0, -95.0),
+            Self::SouthAmerican1956 => Helmert::translation(-288.0, 175.0, -376.0),
+            Self::SouthAmerican1969 => Helmert::translation(-57.0, 1.0, -41.0),
+            Self::Tokyo => Helmert::translation(-148.0, 507.0, 685.0),
+            Self::Wgs1972 => Helmert {
+                translation: [0.0, 0.0, 4.5],
+                rotation: [0.0, 0.0, 0.554],
+                scale: 0.219,
+            },
+        }
+    }
+}
+
+/// Earth centered cartesian coordinates of a latitude and longitude in degrees and an ellipsoidal height in meters
+fn to_geocentric(ellipsoid: Ellipsoid, latitude: f64, longitude: f64, height: f64) -> [f64; 3] {
+    let e2 = ellipsoid.flattening * (2.0 - ellipsoid.flattening);
+    let (phi, lambda) = (latitude.to_radians(), longitude.to_radians());
+    let radius = ellipsoid.semi_major_axis / (1.0 - e2 * phi.sin() * phi.sin()).sqrt();
+    [
+        (radius + height) * phi.cos() * lambda.cos(),
+        (radius + height) * phi.cos() * lambda.sin(),
+        (radius * (1.0 - e2) + height) * phi.sin(),
+    ]
+}
+
+/// Latitude and longitude in degrees of earth centered cartesian coordinates
+fn from_geocentric(ellipsoid: Ellipsoid, [x, y, z]: [f64; 3]) -> (f64, f64) {
+    let e2 = ellipsoid.flattening * (2.0 - ellipsoid.flattening);
+    let p = x.hypot(y);
+    // Converges to well under a millimeter in a few iterations anywhere near the surface
+    let mut phi = z.atan2(p * (1.0 - e2));
+    for _ in 0..6 {
+        let radius = ellipsoid.semi_major_axis / (1.0 - e2 * phi.sin() * phi.sin()).sqrt();
+        phi = (z + e2 * radius * phi.sin()).atan2(p);
+    }
+    (phi.to_degrees(), y.atan2(x).to_degrees())
+}
+
+/// Move a latitude and longitude in degrees from one datum to another
+/// The point is taken to lie on the source ellipsoid, heights are not shifted.
+/// # Returns
+/// `(latitude, longitude)` in degrees on the target datum
+pub(crate) fn shift(from: Datum, to: Datum, latitude: f64, longitude: f64) -> (f64, f64) {
+    if from == to {
+        return (latitude, longitude);
+    }
+    let geocentric = to_geocentric(from.ellipsoid(), latitude, longitude, 0.0);
+    let wgs84 = from.to_wgs84().apply(geocentric);
+    from_geocentric(to.ellipsoid(), to.to_wgs84().inverse().apply(wgs84))
+}
+
+#[cfg(test)]
+mod tests {
+    use float_eq::assert_float_eq;
+
+    use super::*;
+
+    fn degrees(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+        degrees.signum() * (degrees.abs() + minutes / 60.0 + seconds / 3600.0)
+    }
+
+    #[test]
+    fn geocentric_round_trip() {
+        let ellipsoid = Datum::NorthAmerican1927.ellipsoid();
+        let xyz = to_geocentric(ellipsoid, 39.5, -106.6, 0.0);
+        let (latitude, longitude) = from_geocentric(ellipsoid, xyz);
+        assert_float_eq!(latitude, 39.5, abs <= 1e-10);
+        assert_float_eq!(longitude, -106.6, abs <= 1e-10);
+    }
+
+    #[test]
+    fn meades_ranch() {
+        // The NAD27 origin in Kansas, with its published NAD83 position
+        let (latitude, longitude) = shift(
+            Datum::NorthAmerican1927,
+            Datum::NorthAmerican1983,
+            degrees(39.0, 13.0, 26.686),
+            degrees(-98.0, 32.0, 30.506),
+        );
+        // The continental mean translation is good to about five meters
+        assert_float_eq!(latitude, degrees(39.0, 13.0, 26.712_20), abs <= 5e-5);
+        assert_float_eq!(longitude, degrees(-98.0, 32.0, 31.745_40), abs <= 5e-5);
+    }
+
+    #[test]
+    fn greenwich_meridian() {
+        // The Airy transit circle at Greenwich, 5.31 seconds west of the WGS84 prime meridian,
+        // lies within a few meters of zero longitude on OSGB36
+        let (latitude, longitude) = shift(
+            Datum::Wgs1984,
+            Datum::OrdinanceSurvey1936,
+            degrees(51.0, 28.0, 40.12),
+            -5.31 / 3600.0,
+        );
+        assert_float_eq!(longitude, 0.0, abs <= 1.5e-4);
+        assert_float_eq!(latitude, 51.4773, abs <= 1e-4);
+    }
+
+    #[test]
+    fn tokyo_datum_origin() {
+        // The Japanese geodetic datum origin on the Tokyo datum and on JGD2000, which matches WGS84
+        let (latitude, longitude) = shift(
+            Datum::Tokyo,
+            Datum::Wgs1984,
+            degrees(35.0, 39.0, 17.5148),
+            degrees(139.0, 44.0, 40.5020),
+        );
+        assert_float_eq!(latitude, degrees(35.0, 39.0, 29.1572), abs <= 2e-4);
+        assert_float_eq!(longitude, degrees(139.0, 44.0, 28.8759), abs <= 2e-4);
+    }
+
+    #[test]
+    fn shift_and_back() {
+        let (latitude, longitude) = shift(Datum::Wgs1984, Datum::Wgs1972, 39.5, -106.6);
+        assert!((latitude - 39.5).abs() > 1e-7);
+        let (latitude, longitude) = shift(Datum::Wgs1972, Datum::Wgs1984, latitude, longitude);
+        assert_float_eq!(latitude, 39.5, abs <= 1e-8);
+        assert_float_eq!(longitude, -106.6, abs <= 1e-8);
+    }
+}
@@ -0,0 +1,633 @@
+//! Geodesy
+//!
+//! Conversions between the UTM grid coordinates Compass works in and latitude and longitude,
+//! on the ellipsoid of each datum Compass supports, and shifts between those datums.
+//! Elevations are carried through unchanged, no geoid model is applied.
+
+mod datum_shift;
+mod transverse_mercator;
+
+pub(crate) use transverse_mercator::{grid_convergence, reproject_to_zone};
+
+use std::collections::HashMap;
+
+use crate::{
+    project::Loaded, Datum, EastNorthElevation, Error, Hemisphere, Project, StationFix, UtmLocation,
+};
+
+/// A reference ellipsoid, described by its semi-major axis in meters and its flattening
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Ellipsoid {
+    pub(crate) semi_major_axis: f64,
+    pub(crate) flattening: f64,
+}
+
+pub(crate) const AIRY_1830: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_377_563.396,
+    flattening: 1.0 / 299.324_964_6,
+};
+pub(crate) const AUSTRALIAN_NATIONAL: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_160.0,
+    flattening: 1.0 / 298.25,
+};
+pub(crate) const BESSEL_1841: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_377_397.155,
+    flattening: 1.0 / 299.152_812_8,
+};
+pub(crate) const CLARKE_1866: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_206.4,
+    flattening: 1.0 / 294.978_698_2,
+};
+pub(crate) const CLARKE_1880: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_249.145,
+    flattening: 1.0 / 293.465,
+};
+pub(crate) const EVEREST_1830: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_377_276.345,
+    flattening: 1.0 / 300.801_7,
+};
+pub(crate) const GRS80: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_137.0,
+    flattening: 1.0 / 298.257_222_101,
+};
+pub(crate) const INTERNATIONAL_1924: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_388.0,
+    flattening: 1.0 / 297.0,
+};
+pub(crate) const KRASSOVSKY_1940: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_245.0,
+    flattening: 1.0 / 298.3,
+};
+pub(crate) const WGS72: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_135.0,
+    flattening: 1.0 / 298.26,
+};
+pub(crate) const WGS84: Ellipsoid = Ellipsoid {
+    semi_major_axis: 6_378_137.0,
+    flattening: 1.0 / 298.257_223_563,
+};
+
+impl Datum {
+    /// The ellipsoid latitudes and longitudes on the datum are measured on
+    pub(crate) fn ellipsoid(self) -> Ellipsoid {
+        match self {
+            Self::Adindan | Self::Arc1950 | Self::Arc1960 | Self::Cape | Self::Oman => CLARKE_1880,
+            Self::Australian1966 | Self::Australian1984 | Self::SouthAmerican1969 => {
+                AUSTRALIAN_NATIONAL
+            }
+            Self::CampAreaAstro
+            | Self::European1950
+            | Self::European1979
+            | Self::Geodetic1949
+            | Self::HongKong1963
+            | Self::HuTzuShan
+            | Self::SouthAmerican1956 => INTERNATIONAL_1924,
+            Self::Indian => EVEREST_1830,
+            Self::NorthAmerican1927 => CLARKE_1866,
+            Self::NorthAmerican1983 => GRS80,
+            Self::OrdinanceSurvey1936 => AIRY_1830,
+            Self::Pulkovo1942 => KRASSOVSKY_1940,
+            Self::Tokyo => BESSEL_1841,
+            Self::Wgs1972 => WGS72,
+            Self::Wgs1984 => WGS84,
+        }
+    }
+}
+
+/// A location given by latitude and longitude, as used on maps and by GPS receivers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeographicLocation {
+    /// Degrees north of the equator, negative in the southern hemisphere
+    pub latitude: f64,
+    /// Degrees east of Greenwich, negative to the west
+    pub longitude: f64,
+    /// Elevation in meters, as given with the UTM coordinates
+    pub elevation: f64,
+}
+
+impl GeographicLocation {
+    /// Move the location from one datum to another
+    /// Shifts are good to a few meters for datums with published seven parameter transformations
+    /// (Ordnance Survey 1936, WGS 1972, NAD83 and WGS 1984), and to 5 to 25 meters for the rest.
+    /// # Arguments
+    /// - `from` - Datum the location is on
+    /// - `to` - Datum to move the location onto
+    #[must_use]
+    pub fn to_datum(&self, from: Datum, to: Datum) -> Self {
+        let (latitude, longitude) = datum_shift::shift(from, to, self.latitude, self.longitude);
+        Self {
+            latitude,
+            longitude,
+            elevation: self.elevation,
+        }
+    }
+}
+
+/// Largest difference between a stored and computed convergence angle accepted as valid, in degrees
+/// Compass rounds to thousandths of a degree and uses a slightly simpler formula.
+const CONVERGENCE_TOLERANCE: f64 = 0.01;
+/// UTM is only defined between 80 degrees south and 84 degrees north
+const SOUTHERN_LIMIT: f64 = -80.0;
+const NORTHERN_LIMIT: f64 = 84.0;
+
+/// The standard UTM zone containing a longitude in degrees
+fn standard_zone(longitude: f64) -> u8 {
+    let zone = ((longitude + 180.0).rem_euclid(360.0) / 6.0).floor();
+    // Truncation is fine, the zone index is between 0 and 59 and 180 degrees east wraps around to zone 1
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let zone = zone as u8;
+    zone.min(59) + 1
+}
+
+impl UtmLocation {
+    /// Create a UTM location, computing its convergence angle
+    /// # Arguments
+    /// - `east_north_elevation` - Grid coordinates in meters
+    /// - `zone` - UTM zone, from 1 to 60
+    /// - `hemisphere` - Hemisphere the northing is measured in
+    /// - `datum` - Datum the coordinates are on
+    #[must_use]
+    pub fn new(
+        east_north_elevation: EastNorthElevation,
+        zone: u8,
+        hemisphere: Hemisphere,
+        datum: Datum,
+    ) -> Self {
+        let mut location = Self {
+            east_north_elevation,
+            zone,
+            hemisphere,
+            convergence_angle: 0.0,
+        };
+        location.convergence_angle = location.grid_convergence(datum);
+        location
+    }
+
+    /// Compute the angle from true north to grid north at the location
+    /// # Returns
+    /// The convergence in degrees, with the same sign convention as [`UtmLocation::convergence_angle`]
+    #[must_use]
+    pub fn grid_convergence(&self, datum: Datum) -> f64 {
+        let location = self.to_geographic(datum);
+        grid_convergence(
+            datum.ellipsoid(),
+            self.zone,
+            location.latitude,
+            location.longitude,
+        )
+    }
+
+    /// Compute the point scale factor at the location
+    /// # Returns
+    /// Grid distance divided by distance on the ellipsoid, 0.9996 on the central meridian
+    #[must_use]
+    pub fn scale_factor(&self, datum: Datum) -> f64 {
+        let location = self.to_geographic(datum);
+        transverse_mercator::point_scale_factor(
+            datum.ellipsoid(),
+            self.zone,
+            location.latitude,
+            location.longitude,
+        )
+    }
+
+    /// Check that the location is on the UTM grid and that its convergence angle matches its position
+    /// # Errors
+    /// - [`Error::InvalidBaseLocation`] If the zone, coordinates or convergence angle are out of range
+    pub fn validate(&self, datum: Datum) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::InvalidBaseLocation(message));
+        if !(1..=60).contains(&self.zone) {
+            return invalid(format!("zone {} is not between 1 and 60", self.zone));
+        }
+        let ene = self.east_north_elevation;
+        if !(0.0..1_000_000.0).contains(&ene.easting) {
+            return invalid(format!("easting {:.3} is off the grid", ene.easting));
+        }
+        if !(0.0..=10_000_000.0).contains(&ene.northing) {
+            return invalid(format!("northing {:.3} is off the grid", ene.northing));
+        }
+        let location = self.to_geographic(datum);
+        if !(SOUTHERN_LIMIT..=NORTHERN_LIMIT).contains(&location.latitude) {
+            return invalid(format!(
+                "latitude {:.3} is outside the UTM grid",
+                location.latitude
+            ));
+        }
+        let computed = self.grid_convergence(datum);
+        if (self.convergence_angle - computed).abs() > CONVERGENCE_TOLERANCE {
+            return invalid(format!(
+                "convergence angle {:.3} should be {computed:.3}",
+                self.convergence_angle
+            ));
+        }
+        Ok(())
+    }
+
+    /// Convert the location to latitude and longitude
+    /// # Arguments
+    /// - `datum` - Datum the UTM coordinates are on, the result is on the same datum
+    #[must_use]
+    pub fn to_geographic(&self, datum: Datum) -> GeographicLocation {
+        let (latitude, longitude) = transverse_mercator::to_geographic(
+            datum.ellipsoid(),
+            self.zone,
+            self.hemisphere,
+            self.east_north_elevation.easting,
+            self.east_north_elevation.northing,
+        );
+        GeographicLocation {
+            latitude,
+            longitude,
+            elevation: self.east_north_elevation.up,
+        }
+    }
+
+    /// Project a latitude and longitude onto the UTM grid
+    /// The hemisphere is taken from the sign of the latitude, and the convergence angle is computed for the location.
+    /// # Arguments
+    /// - `location` - Latitude and longitude on `datum`
+    /// - `datum` - Datum of the location, the result is on the same datum
+    /// - `zone` - Zone to project into, or `None` for the standard zone containing the longitude
+    #[must_use]
+    pub fn from_geographic(location: GeographicLocation, datum: Datum, zone: Option<u8>) -> Self {
+        let zone = zone.unwrap_or_else(|| standard_zone(location.longitude));
+        let hemisphere = if location.latitude < 0.0 {
+            Hemisphere::South
+        } else {
+            Hemisphere::North
+        };
+        let ellipsoid = datum.ellipsoid();
+        let (easting, northing) = transverse_mercator::from_geographic(
+            ellipsoid,
+            zone,
+            hemisphere,
+            location.latitude,
+            location.longitude,
+        );
+        Self {
+            east_north_elevation: EastNorthElevation::from_meters(
+                easting,
+                northing,
+                location.elevation,
+            ),
+            zone,
+            hemisphere,
+            convergence_angle: grid_convergence(
+                ellipsoid,
+                zone,
+                location.latitude,
+                location.longitude,
+            ),
+        }
+    }
+}
+
+impl UtmLocation {
+    /// Move the location from one datum's grid to another's, keeping its zone and hemisphere
+    /// The convergence angle is recomputed on the new grid.
+    /// # Arguments
+    /// - `from` - Datum the location is on
+    /// - `to` - Datum to move the location onto
+    #[must_use]
+    pub fn to_datum(&self, from: Datum, to: Datum) -> Self {
+        if from == to {
+            return *self;
+        }
+        let location = self.to_geographic(from).to_datum(from, to);
+        let (easting, northing) = transverse_mercator::from_geographic(
+            to.ellipsoid(),
+            self.zone,
+            self.hemisphere,
+            location.latitude,
+            location.longitude,
+        );
+        Self {
+            east_north_elevation: EastNorthElevation::from_meters(
+                easting,
+                northing,
+                location.elevation,
+            ),
+            convergence_angle: grid_convergence(
+                to.ellipsoid(),
+                self.zone,
+                location.latitude,
+                location.longitude,
+            ),
+            ..*self
+        }
+    }
+}
+
+impl<S> Project<S> {
+    /// Check the base location against the project's datum, see [`UtmLocation::validate`]
+    /// # Errors
+    /// - [`Error::InvalidBaseLocation`] If the base location is off the grid or its convergence angle doesn't match it
+    pub fn validate_base_location(&self) -> Result<(), Error> {
+        self.base_location.validate(self.datum)
+    }
+
+    /// Move the project onto another datum
+    /// The base location and every fixed station are reprojected, keeping their UTM zones.
+    /// Survey data is relative to the fixed stations, so it is unaffected.
+    pub fn change_datum(&mut self, datum: Datum) {
+        let from = self.datum;
+        self.base_location = self.base_location.to_datum(from, datum);
+        let fixed_station_grid = UtmLocation {
+            zone: self.fixed_station_zone(),
+            ..self.base_location
+        };
+        for station in self
+            .survey_files
+            .iter_mut()
+            .flat_map(|survey_file| &mut survey_file.project_stations)
+        {
+            if let Some(fix) = station.fix() {
+                let location = UtmLocation {
+                    east_north_elevation: fix.location(),
+                    ..fixed_station_grid
+                }
+                .to_datum(from, datum);
+                station.set_fix(Some(StationFix::new(
+                    location.east_north_elevation,
+                    fix.units(),
+                )));
+            }
+        }
+        self.datum = datum;
+    }
+
+    /// Convert a location on the project's grid to latitude and longitude on the project's datum
+    /// # Arguments
+    /// - `location` - Coordinates in meters in the base location's UTM zone and hemisphere,
+    ///   such as those from [`Project::station_coordinates`]
+    #[must_use]
+    pub fn to_geographic(&self, location: EastNorthElevation) -> GeographicLocation {
+        UtmLocation {
+            east_north_elevation: location,
+            ..self.base_location
+        }
+        .to_geographic(self.datum)
+    }
+
+    /// Project a latitude and longitude on the project's datum onto the project's grid
+    /// # Returns
+    /// Coordinates in meters in the base location's UTM zone and hemisphere
+    #[must_use]
+    pub fn from_geographic(&self, location: GeographicLocation) -> EastNorthElevation {
+        let (easting, northing) = transverse_mercator::from_geographic(
+            self.datum.ellipsoid(),
+            self.base_location.zone,
+            self.base_location.hemisphere,
+            location.latitude,
+            location.longitude,
+        );
+        EastNorthElevation::from_meters(easting, northing, location.elevation)
+    }
+}
+
+impl Project<Loaded> {
+    /// Compute the latitude and longitude of every station in the project, on the project's datum
+    /// Stations are positioned as by [`Project::station_coordinates`].
+    /// # Returns
+    /// Map from station name to its location
+    #[must_use]
+    pub fn station_geographic_coordinates(&self) -> HashMap<String, GeographicLocation> {
+        self.station_coordinates()
+            .into_iter()
+            .map(|(station, location)| (station, self.to_geographic(location)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use float_eq::assert_float_eq;
+
+    use super::*;
+
+    #[test]
+    fn utm_to_geographic() {
+        // The CN Tower in Toronto
+        let location = GeographicLocation {
+            latitude: 43.642_567,
+            longitude: -79.387_139,
+            elevation: 76.0,
+        };
+        let utm = UtmLocation::from_geographic(location, Datum::Wgs1984, None);
+        assert_eq!(utm.zone, 17);
+        assert_eq!(utm.hemisphere, Hemisphere::North);
+        assert_float_eq!(utm.east_north_elevation.easting, 630_084.0, abs <= 1.0);
+        assert_float_eq!(utm.east_north_elevation.northing, 4_833_439.0, abs <= 1.0);
+        assert_float_eq!(utm.east_north_elevation.up, 76.0, abs <= f64::EPSILON);
+
+        let round_trip = utm.to_geographic(Datum::Wgs1984);
+        assert_float_eq!(round_trip.latitude, location.latitude, abs <= 1e-9);
+        assert_float_eq!(round_trip.longitude, location.longitude, abs <= 1e-9);
+    }
+
+    #[test]
+    fn southern_hemisphere() {
+        let location = GeographicLocation {
+            latitude: -33.924_87,
+            longitude: 18.424_06,
+            elevation: 0.0,
+        };
+        let utm = UtmLocation::from_geographic(location, Datum::Wgs1984, None);
+        assert_eq!(utm.zone, 34);
+        assert_eq!(utm.hemisphere, Hemisphere::South);
+        assert_float_eq!(utm.east_north_elevation.easting, 261_878.0, abs <= 1.0);
+        assert_float_eq!(utm.east_north_elevation.northing, 6_243_186.0, abs <= 1.0);
+        let round_trip = utm.to_geographic(Datum::Wgs1984);
+        assert_float_eq!(round_trip.latitude, location.latitude, abs <= 1e-9);
+    }
+
+    #[test]
+    fn datum_ellipsoids() {
+        let location = GeographicLocation {
+            latitude: 39.493_388,
+            longitude: -106.654_671,
+            elevation: 3048.0,
+        };
+        let wgs84 = UtmLocation::from_geographic(location, Datum::Wgs1984, Some(13));
+        let nad83 = UtmLocation::from_geographic(location, Datum::NorthAmerican1983, Some(13));
+        let nad27 = UtmLocation::from_geographic(location, Datum::NorthAmerican1927, Some(13));
+        // GRS80 and WGS84 differ by a fraction of a millimeter
+        assert_float_eq!(
+            wgs84.east_north_elevation.northing,
+            nad83.east_north_elevation.northing,
+            abs <= 0.001
+        );
+        // The same coordinates on Clarke 1866 land far enough away to matter
+        assert!(
+            (wgs84.east_north_elevation.northing - nad27.east_north_elevation.northing).abs()
+                > 10.0
+        );
+        for datum in [
+            Datum::NorthAmerican1927,
+            Datum::OrdinanceSurvey1936,
+            Datum::Tokyo,
+        ] {
+            let round_trip =
+                UtmLocation::from_geographic(location, datum, Some(12)).to_geographic(datum);
+            assert_float_eq!(round_trip.latitude, location.latitude, abs <= 1e-9);
+            assert_float_eq!(round_trip.longitude, location.longitude, abs <= 1e-9);
+        }
+    }
+
+    #[test]
+    fn standard_zones() {
+        assert_eq!(standard_zone(-180.0), 1);
+        assert_eq!(standard_zone(-106.65), 13);
+        assert_eq!(standard_zone(0.0), 31);
+        assert_eq!(standard_zone(179.9), 60);
+        assert_eq!(standard_zone(180.0), 1);
+    }
+
+    #[test]
+    fn project_stations() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        let coordinates = project.station_coordinates();
+        let geographic = project.station_geographic_coordinates();
+        assert_eq!(geographic.len(), coordinates.len());
+        for (station, location) in &geographic {
+            let grid = project.from_geographic(*location);
+            let expected = coordinates[station];
+            assert_float_eq!(grid.easting, expected.easting, abs <= 1e-6);
+            assert_float_eq!(grid.northing, expected.northing, abs <= 1e-6);
+            assert_float_eq!(grid.up, expected.up, abs <= f64::EPSILON);
+        }
+        // Fulford Cave is in the mountains of central Colorado
+        let a1 = geographic["A1"];
+        assert!((39.0..40.0).contains(&a1.latitude));
+        assert!((-107.0..-106.0).contains(&a1.longitude));
+    }
+
+    #[test]
+    fn utm_between_datums() {
+        let nad27 = UtmLocation {
+            east_north_elevation: EastNorthElevation::from_meters(357_700.0, 4_372_630.0, 3048.0),
+            zone: 13,
+            hemisphere: Hemisphere::North,
+            convergence_angle: -1.05,
+        };
+        let wgs84 = nad27.to_datum(Datum::NorthAmerican1927, Datum::Wgs1984);
+        assert_eq!(wgs84.zone, 13);
+        assert_float_eq!(
+            wgs84.convergence_angle,
+            wgs84.grid_convergence(Datum::Wgs1984),
+            abs <= 1e-9
+        );
+        assert_float_eq!(wgs84.convergence_angle, -1.05, abs <= 0.005);
+        assert_float_eq!(wgs84.east_north_elevation.up, 3048.0, abs <= f64::EPSILON);
+        // NAD27 grid coordinates in Colorado sit about 200 meters south of WGS84 ones
+        let shift = wgs84.east_north_elevation - nad27.east_north_elevation;
+        assert!((150.0..250.0).contains(&shift.northing));
+        assert!(shift.easting.abs() < 100.0);
+
+        let back = wgs84.to_datum(Datum::Wgs1984, Datum::NorthAmerican1927);
+        assert_float_eq!(
+            back.east_north_elevation.easting,
+            nad27.east_north_elevation.easting,
+            abs <= 0.001
+        );
+        assert_float_eq!(
+            back.east_north_elevation.northing,
+            nad27.east_north_elevation.northing,
+            abs <= 0.001
+        );
+    }
+
+    #[test]
+    fn project_between_datums() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/zone_12_fixes.mak");
+        let mut project = Project::read(&sample_path).unwrap();
+        let base = project.base_location;
+        let a1 = project.fixed_station_locations()["A1"];
+
+        project.change_datum(Datum::NorthAmerican1927);
+        assert_eq!(project.datum, Datum::NorthAmerican1927);
+        assert_eq!(project.base_location.zone, 13);
+        assert_eq!(project.fixed_station_zone(), 12);
+        let shifted_base = project.base_location.east_north_elevation;
+        assert!((shifted_base - base.east_north_elevation).northing < -150.0);
+        // The fix is still written in zone 12, and still lands on the base location
+        let shifted_a1 = project.fixed_station_locations()["A1"];
+        assert_float_eq!(shifted_a1.easting, shifted_base.easting, abs <= 0.01);
+        assert_float_eq!(shifted_a1.northing, shifted_base.northing, abs <= 0.01);
+        assert!((shifted_a1 - a1).northing < -150.0);
+
+        project.change_datum(Datum::NorthAmerican1983);
+        assert_float_eq!(
+            project.base_location.east_north_elevation.easting,
+            base.east_north_elevation.easting,
+            abs <= 0.001
+        );
+        assert_float_eq!(
+            project.base_location.east_north_elevation.northing,
+            base.east_north_elevation.northing,
+            abs <= 0.001
+        );
+    }
+
+    #[test]
+    fn convergence_and_scale() {
+        let location = UtmLocation::new(
+            EastNorthElevation::from_meters(357_715.717, 4_372_837.574, 3048.0),
+            13,
+            Hemisphere::North,
+            Datum::NorthAmerican1983,
+        );
+        // Compass computed -1.050 for the Fulford Cave base location
+        assert_float_eq!(location.convergence_angle, -1.050, abs <= 0.005);
+        assert_float_eq!(
+            location.scale_factor(Datum::NorthAmerican1983),
+            0.999_85,
+            abs <= 0.000_01
+        );
+        assert!(location.validate(Datum::NorthAmerican1983).is_ok());
+
+        let south = UtmLocation::new(
+            EastNorthElevation::from_meters(261_878.0, 6_243_186.0, 0.0),
+            34,
+            Hemisphere::South,
+            Datum::Wgs1984,
+        );
+        assert!(south.convergence_angle > 0.0);
+        assert!(south.validate(Datum::Wgs1984).is_ok());
+    }
+
+    #[test]
+    fn validate_base_locations() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path).unwrap();
+        assert!(project.validate_base_location().is_ok());
+
+        // The example in the Compass documentation has the sign of its convergence angle flipped
+        sample_path.set_file_name("project_file_examples");
+        let example = Project::read(&sample_path).unwrap();
+        assert!(matches!(
+            example.validate_base_location(),
+            Err(Error::InvalidBaseLocation(message)) if message.contains("should be -0.779")
+        ));
+
+        let mut location = project.base_location;
+        location.east_north_elevation.northing = -5.0;
+        assert!(location.validate(project.datum).is_err());
+        location.east_north_elevation.northing = 9_900_000.0;
+        assert!(matches!(
+            location.validate(project.datum),
+            Err(Error::InvalidBaseLocation(message)) if message.starts_with("latitude")
+        ));
+        location.zone = 0;
+        assert!(location.validate(project.datum).is_err());
+    }
+}
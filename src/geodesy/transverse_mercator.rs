@@ -0,0 +1,295 @@
+//! Transverse Mercator projection used by the UTM grid
+//!
+//! Uses the Krüger series, which stays accurate to well under a millimeter several zones
+//! away from the central meridian, so coordinates can be carried across zone boundaries.
+
+use std::f64::consts::PI;
+
+use crate::{EastNorthElevation, Hemisphere};
+
+use super::Ellipsoid;
+
+const SCALE_FACTOR: f64 = 0.9996;
+const FALSE_EASTING: f64 = 500_000.0;
+/// Added to southern hemisphere northings so they stay positive
+const SOUTHERN_FALSE_NORTHING: f64 = 10_000_000.0;
+
+impl Ellipsoid {
+    fn eccentricity(self) -> f64 {
+        (self.flattening * (2.0 - self.flattening)).sqrt()
+    }
+
+    fn third_flattening(self) -> f64 {
+        self.flattening / (2.0 - self.flattening)
+    }
+
+    /// Radius of the rectifying sphere, scaled by the UTM central scale factor
+    fn rectifying_radius(self) -> f64 {
+        let n = self.third_flattening();
+        let n2 = n * n;
+        SCALE_FACTOR * self.semi_major_axis / (1.0 + n)
+            * (1.0 + n2 / 4.0 + n2 * n2 / 64.0 + n2 * n2 * n2 / 256.0)
+    }
+
+    fn forward_coefficients(self) -> [f64; 4] {
+        let n = self.third_flattening();
+        let (n2, n3, n4) = (n * n, n * n * n, n * n * n * n);
+        [
+            n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0 + 41.0 * n4 / 180.0,
+            13.0 * n2 / 48.0 - 3.0 * n3 / 5.0 + 557.0 * n4 / 1440.0,
+            61.0 * n3 / 240.0 - 103.0 * n4 / 140.0,
+            49561.0 * n4 / 161_280.0,
+        ]
+    }
+
+    fn inverse_coefficients(self) -> [f64; 4] {
+        let n = self.third_flattening();
+        let (n2, n3, n4) = (n * n, n * n * n, n * n * n * n);
+        [
+            n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0 - n4 / 360.0,
+            n2 / 48.0 + n3 / 15.0 - 437.0 * n4 / 1440.0,
+            17.0 * n3 / 480.0 - 37.0 * n4 / 840.0,
+            4397.0 * n4 / 161_280.0,
+        ]
+    }
+}
+
+fn false_northing(hemisphere: Hemisphere) -> f64 {
+    match hemisphere {
+        Hemisphere::North => 0.0,
+        Hemisphere::South => SOUTHERN_FALSE_NORTHING,
+    }
+}
+
+/// Longitude of a UTM zone's central meridian in degrees
+pub(crate) fn central_meridian(zone: u8) -> f64 {
+    f64::from(zone) * 6.0 - 183.0
+}
+
+/// Coordinates on the conformal sphere, from which the Krüger series are evaluated
+struct Conformal {
+    /// Tangent of the conformal latitude
+    t: f64,
+    /// Longitude from the central meridian in radians
+    lambda: f64,
+    xi: f64,
+    eta: f64,
+}
+
+impl Conformal {
+    fn new(ellipsoid: Ellipsoid, zone: u8, latitude: f64, longitude: f64) -> Self {
+        let e = ellipsoid.eccentricity();
+        let phi = latitude.to_radians();
+        let lambda = (longitude - central_meridian(zone)).to_radians();
+        let t = (phi.sin().atanh() - e * (e * phi.sin()).atanh()).sinh();
+        Self {
+            t,
+            lambda,
+            xi: t.atan2(lambda.cos()),
+            eta: (lambda.sin() / (1.0 + t * t).sqrt()).atanh(),
+        }
+    }
+
+    /// Real and imaginary parts of the derivative of the series, which give the convergence and scale
+    fn derivative(&self, ellipsoid: Ellipsoid) -> (f64, f64) {
+        let mut p = 1.0;
+        let mut q = 0.0;
+        for (j, alpha) in (1..).zip(ellipsoid.forward_coefficients()) {
+            let k = 2.0 * f64::from(j);
+            p += k * alpha * (k * self.xi).cos() * (k * self.eta).cosh();
+            q += k * alpha * (k * self.xi).sin() * (k * self.eta).sinh();
+        }
+        (p, q)
+    }
+}
+
+/// Project a latitude and longitude in degrees onto a UTM zone
+/// # Returns
+/// `(easting, northing)` in meters
+pub(crate) fn from_geographic(
+    ellipsoid: Ellipsoid,
+    zone: u8,
+    hemisphere: Hemisphere,
+    latitude: f64,
+    longitude: f64,
+) -> (f64, f64) {
+    let Conformal { xi, eta, .. } = Conformal::new(ellipsoid, zone, latitude, longitude);
+    let mut x = eta;
+    let mut y = xi;
+    for (j, alpha) in (1..).zip(ellipsoid.forward_coefficients()) {
+        let k = 2.0 * f64::from(j);
+        x += alpha * (k * xi).cos() * (k * eta).sinh();
+        y += alpha * (k * xi).sin() * (k * eta).cosh();
+    }
+    let radius = ellipsoid.rectifying_radius();
+    (
+        FALSE_EASTING + radius * x,
+        false_northing(hemisphere) + radius * y,
+    )
+}
+
+/// Angle from true north to grid north at a latitude and longitude in degrees, for a UTM zone
+/// # Returns
+/// The convergence in degrees, positive east of the central meridian in the northern hemisphere
+pub(crate) fn grid_convergence(
+    ellipsoid: Ellipsoid,
+    zone: u8,
+    latitude: f64,
+    longitude: f64,
+) -> f64 {
+    let conformal = Conformal::new(ellipsoid, zone, latitude, longitude);
+    let (p, q) = conformal.derivative(ellipsoid);
+    let t = conformal.t;
+    let gamma = (t / (1.0 + t * t).sqrt() * conformal.lambda.tan()).atan() + q.atan2(p);
+    gamma * 180.0 / PI
+}
+
+/// Ratio of a short grid distance to the true distance on the ellipsoid at a latitude and longitude in degrees
+/// 0.9996 on the central meridian, growing to about 1.001 at the edges of a zone
+pub(crate) fn point_scale_factor(
+    ellipsoid: Ellipsoid,
+    zone: u8,
+    latitude: f64,
+    longitude: f64,
+) -> f64 {
+    let e = ellipsoid.eccentricity();
+    let phi = latitude.to_radians();
+    let conformal = Conformal::new(ellipsoid, zone, latitude, longitude);
+    let (p, q) = conformal.derivative(ellipsoid);
+    let t = conformal.t;
+    (1.0 - (e * phi.sin()).powi(2)).sqrt() * (1.0 + phi.tan().powi(2)).sqrt()
+        / (t * t + conformal.lambda.cos().powi(2)).sqrt()
+        * ellipsoid.rectifying_radius()
+        / ellipsoid.semi_major_axis
+        * p.hypot(q)
+}
+
+/// Convert UTM coordinates in meters to latitude and longitude
+/// # Returns
+/// `(latitude, longitude)` in degrees
+pub(crate) fn to_geographic(
+    ellipsoid: Ellipsoid,
+    zone: u8,
+    hemisphere: Hemisphere,
+    easting: f64,
+    northing: f64,
+) -> (f64, f64) {
+    let e = ellipsoid.eccentricity();
+    let radius = ellipsoid.rectifying_radius();
+    let xi = (northing - false_northing(hemisphere)) / radius;
+    let eta = (easting - FALSE_EASTING) / radius;
+
+    let mut xi_prime = xi;
+    let mut eta_prime = eta;
+    for (j, beta) in (1..).zip(ellipsoid.inverse_coefficients()) {
+        let k = 2.0 * f64::from(j);
+        xi_prime -= beta * (k * xi).sin() * (k * eta).cosh();
+        eta_prime -= beta * (k * xi).cos() * (k * eta).sinh();
+    }
+    let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+    // The conformal latitude converges on the geodetic latitude in a handful of iterations
+    let mut phi = chi;
+    for _ in 0..8 {
+        phi = (chi.sin().atanh() + e * (e * phi.sin()).atanh())
+            .tanh()
+            .asin();
+    }
+    let lambda = eta_prime.sinh().atan2(xi_prime.cos());
+    (
+        phi * 180.0 / PI,
+        central_meridian(zone) + lambda * 180.0 / PI,
+    )
+}
+
+/// Move a location from one UTM zone's grid onto another's in the same hemisphere, keeping its elevation
+pub(crate) fn reproject_to_zone(
+    ellipsoid: Ellipsoid,
+    hemisphere: Hemisphere,
+    location: EastNorthElevation,
+    from_zone: u8,
+    to_zone: u8,
+) -> EastNorthElevation {
+    if from_zone == to_zone {
+        return location;
+    }
+    let (latitude, longitude) = to_geographic(
+        ellipsoid,
+        from_zone,
+        hemisphere,
+        location.easting,
+        location.northing,
+    );
+    let (easting, northing) = from_geographic(ellipsoid, to_zone, hemisphere, latitude, longitude);
+    EastNorthElevation::from_meters(easting, northing, location.up)
+}
+
+#[cfg(test)]
+mod tests {
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::geodesy::WGS84;
+
+    #[test]
+    fn round_trip() {
+        let (easting, northing) =
+            from_geographic(WGS84, 13, Hemisphere::North, 39.493_388, -106.654_671);
+        assert_float_eq!(easting, 357_715.72, abs <= 0.01);
+        assert_float_eq!(northing, 4_372_837.57, abs <= 0.01);
+        let (latitude, longitude) = to_geographic(WGS84, 13, Hemisphere::North, easting, northing);
+        assert_float_eq!(latitude, 39.493_388, abs <= 1e-9);
+        assert_float_eq!(longitude, -106.654_671, abs <= 1e-9);
+    }
+
+    #[test]
+    fn reproject_into_neighbouring_zone() {
+        let zone_12 = EastNorthElevation::from_meters(873_711.528, 4_380_552.980, 3048.0);
+        let zone_13 = reproject_to_zone(WGS84, Hemisphere::North, zone_12, 12, 13);
+        assert_float_eq!(zone_13.easting, 357_715.717, abs <= 0.002);
+        assert_float_eq!(zone_13.northing, 4_372_837.574, abs <= 0.002);
+        assert_float_eq!(zone_13.up, 3048.0, abs <= f64::EPSILON);
+    }
+
+    #[test]
+    fn southern_hemisphere() {
+        // Cape Town, zone 34H
+        let (easting, northing) =
+            from_geographic(WGS84, 34, Hemisphere::South, -33.924_87, 18.424_06);
+        assert_float_eq!(easting, 261_878.0, abs <= 1.0);
+        assert_float_eq!(northing, 6_243_186.0, abs <= 1.0);
+        let (latitude, longitude) = to_geographic(WGS84, 34, Hemisphere::South, easting, northing);
+        assert_float_eq!(latitude, -33.924_87, abs <= 1e-9);
+        assert_float_eq!(longitude, 18.424_06, abs <= 1e-9);
+    }
+
+    #[test]
+    fn convergence() {
+        assert_float_eq!(
+            grid_convergence(WGS84, 13, 39.0, central_meridian(13)),
+            0.0,
+            abs <= 1e-12
+        );
+        // Close to the spherical approximation of the longitude offset times the sine of the latitude
+        let gamma = grid_convergence(WGS84, 13, 39.493_388, -106.654_671);
+        let approximate = (-106.654_671 - central_meridian(13)) * 39.493_388f64.to_radians().sin();
+        assert_float_eq!(gamma, approximate, abs <= 0.01);
+        assert!(gamma < 0.0);
+        assert!(grid_convergence(WGS84, 34, -33.924_87, 18.424_06) > 0.0);
+    }
+
+    #[test]
+    fn scale_factor() {
+        assert_float_eq!(
+            point_scale_factor(WGS84, 13, 0.0, central_meridian(13)),
+            SCALE_FACTOR,
+            abs <= 1e-12
+        );
+        // Close to the spherical approximation k0 / cos of the angular distance from the central meridian
+        let k = point_scale_factor(WGS84, 13, 39.493_388, -106.654_671);
+        let offset = ((-106.654_671 - central_meridian(13)).to_radians()
+            * 39.493_388f64.to_radians().cos())
+        .cos();
+        assert_float_eq!(k, SCALE_FACTOR / offset, abs <= 1e-5);
+        assert!(point_scale_factor(WGS84, 13, 39.5, -102.0) > 1.0);
+    }
+}
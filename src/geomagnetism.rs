@@ -0,0 +1,649 @@
+//! Geomagnetic model
+//!
+//! Computes magnetic declination from the International Geomagnetic Reference Field,
+//! to check or fill in the declinations recorded with surveys.
+//! The 14th generation table is bundled, see [`GeomagneticModel::igrf`].
+//! Other coefficient tables in the format IAGA publishes with each IGRF generation,
+//! such as `igrf14coeffs.txt`, can be read so the model can be kept current without a new release.
+
+use std::{f64::consts::PI, path::Path};
+
+use crate::{project::Loaded, Date, Datum, Error, GeographicLocation, Project, Survey, SurveyFile};
+
+/// Mean radius of the earth used as the reference radius of the IGRF, in kilometers
+const REFERENCE_RADIUS: f64 = 6_371.2;
+/// WGS84 semi-major and semi-minor axes in kilometers, for converting to geocentric coordinates
+const SEMI_MAJOR_AXIS: f64 = 6_378.137;
+const SEMI_MINOR_AXIS: f64 = 6_356.752_314_2;
+/// The model is extrapolated with its secular variation for this many years past its last epoch
+const EXTRAPOLATION_YEARS: f64 = 5.0;
+/// IGRF-14 coefficients from 1900 to 2025, with the secular variation for 2025 to 2030
+const IGRF_14: &str = include_str!("../data/igrf14coeffs.txt");
+
+/// Gauss coefficients of the main field at one epoch, in nanotesla
+/// Stored as `g[n][m]` and `h[n][m]`, with degree `n` from 1
+#[derive(Clone, Debug, PartialEq)]
+struct Coefficients {
+    g: Vec<Vec<f64>>,
+    h: Vec<Vec<f64>>,
+}
+
+impl Coefficients {
+    fn zero(degree: usize) -> Self {
+        let triangle: Vec<Vec<f64>> = (0..=degree).map(|n| vec![0.0; n + 1]).collect();
+        Self {
+            g: triangle.clone(),
+            h: triangle,
+        }
+    }
+
+    /// Linear combination `self + (other - self) * fraction`, also used to extrapolate with secular variation
+    fn interpolate(&self, other: &Self, fraction: f64) -> Self {
+        let blend = |a: &Vec<Vec<f64>>, b: &Vec<Vec<f64>>| {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| {
+                    a.iter()
+                        .zip(b)
+                        .map(|(a, b)| a + (b - a) * fraction)
+                        .collect()
+                })
+                .collect()
+        };
+        Self {
+            g: blend(&self.g, &other.g),
+            h: blend(&self.h, &other.h),
+        }
+    }
+
+    fn add_scaled(&self, rate: &Self, years: f64) -> Self {
+        let add = |a: &Vec<Vec<f64>>, b: &Vec<Vec<f64>>| {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| a.iter().zip(b).map(|(a, b)| a + b * years).collect())
+                .collect()
+        };
+        Self {
+            g: add(&self.g, &rate.g),
+            h: add(&self.h, &rate.h),
+        }
+    }
+}
+
+/// A spherical harmonic model of the earth's main magnetic field, loaded from an IGRF coefficient table
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeomagneticModel {
+    /// Decimal years of each epoch in the table, in increasing order
+    epochs: Vec<f64>,
+    coefficients: Vec<Coefficients>,
+    /// Rate of change after the last epoch, in nanotesla per year
+    secular_variation: Coefficients,
+}
+
+/// A degree or order as a float, published tables only go to degree 13
+fn float(index: usize) -> f64 {
+    f64::from(u32::try_from(index).unwrap_or(u32::MAX))
+}
+
+/// Schmidt semi-normalized associated Legendre functions of `cos(theta)` and their derivatives by `theta`
+fn legendre(degree: usize, cos_theta: f64, sin_theta: f64) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let mut p: Vec<Vec<f64>> = (0..=degree).map(|n| vec![0.0; n + 1]).collect();
+    let mut dp = p.clone();
+    p[0][0] = 1.0;
+    for n in 1..=degree {
+        let nf = float(n);
+        // Sectoral terms from the previous degree's
+        if n == 1 {
+            p[1][1] = sin_theta;
+            dp[1][1] = cos_theta;
+        } else {
+            let k = ((2.0 * nf - 1.0) / (2.0 * nf)).sqrt();
+            p[n][n] = k * sin_theta * p[n - 1][n - 1];
+            dp[n][n] = k * (cos_theta * p[n - 1][n - 1] + sin_theta * dp[n - 1][n - 1]);
+        }
+        for m in 0..n {
+            let mf = float(m);
+            let scale = (nf * nf - mf * mf).sqrt();
+            let previous = (2.0 * nf - 1.0) * cos_theta * p[n - 1][m];
+            let previous_derivative =
+                (2.0 * nf - 1.0) * (cos_theta * dp[n - 1][m] - sin_theta * p[n - 1][m]);
+            let (before, before_derivative) = if n >= 2 && m <= n - 2 {
+                let k = ((nf - 1.0) * (nf - 1.0) - mf * mf).sqrt();
+                (k * p[n - 2][m], k * dp[n - 2][m])
+            } else {
+                (0.0, 0.0)
+            };
+            p[n][m] = (previous - before) / scale;
+            dp[n][m] = (previous_derivative - before_derivative) / scale;
+        }
+    }
+    (p, dp)
+}
+
+/// Decimal year at the middle of a date, with two digit years read as 19xx
+fn decimal_year(date: Date) -> f64 {
+    let full_year = date.full_year();
+    let year = i32::from(full_year);
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let month_lengths = [
+        31,
+        if leap { 29 } else { 28 },
+        31,
+        30,
+        31,
+        30,
+        31,
+        31,
+        30,
+        31,
+        30,
+        31,
+    ];
+    let month = usize::from(date.month.clamp(1, 12));
+    let day_of_year: u16 = month_lengths[..month - 1].iter().sum::<u16>() + u16::from(date.day);
+    let days_in_year = if leap { 366.0 } else { 365.0 };
+    f64::from(full_year) + (f64::from(day_of_year) - 0.5) / days_in_year
+}
+
+fn parse_error(line_number: usize, message: &str) -> Error {
+    Error::CouldntParseGeomagneticModel(format!("line {}: {message}", line_number + 1))
+}
+
+impl GeomagneticModel {
+    /// The bundled 14th generation IGRF, covering 1900 to 2030
+    #[must_use]
+    pub fn igrf() -> Self {
+        match Self::parse(IGRF_14) {
+            Ok(model) => model,
+            Err(e) => unreachable!("bundled IGRF table is malformed: {e}"),
+        }
+    }
+
+    /// Read an IGRF coefficient table from disk
+    /// # Errors
+    /// - [`Error::CouldntReadFile`] If the file cannot be read
+    /// - [`Error::CouldntParseGeomagneticModel`] If the file isn't an IGRF coefficient table
+    pub fn read(file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(file_path).map_err(Error::CouldntReadFile)?;
+        Self::parse(&contents)
+    }
+
+    /// Parse an IGRF coefficient table
+    /// The table has a `g/h n m` header row naming each epoch, with the secular variation last,
+    /// followed by one row per coefficient. Lines starting with `#` and the row above the header are ignored.
+    /// # Errors
+    /// - [`Error::CouldntParseGeomagneticModel`] If the table is malformed
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let mut epochs: Option<Vec<f64>> = None;
+        let mut rows = Vec::new();
+        for (line_number, line) in input.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("g/h") => {
+                    let columns: Vec<_> = fields.skip(2).collect();
+                    let Some((_, epoch_columns)) = columns.split_last() else {
+                        return Err(parse_error(line_number, "no epochs in header"));
+                    };
+                    let parsed = epoch_columns
+                        .iter()
+                        .map(|epoch| epoch.parse::<f64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| parse_error(line_number, "invalid epoch"))?;
+                    if parsed.is_empty() || parsed.windows(2).any(|pair| pair[0] >= pair[1]) {
+                        return Err(parse_error(line_number, "epochs must increase"));
+                    }
+                    epochs = Some(parsed);
+                }
+                Some(kind @ ("g" | "h")) => {
+                    let values = fields
+                        .map(str::parse::<f64>)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| parse_error(line_number, "invalid number"))?;
+                    rows.push((line_number, kind == "g", values));
+                }
+                _ => (),
+            }
+        }
+        let epochs = epochs
+            .ok_or_else(|| Error::CouldntParseGeomagneticModel("missing g/h header".to_string()))?;
+
+        let mut indexed = Vec::with_capacity(rows.len());
+        let mut degree = 0;
+        for (line_number, is_g, values) in rows {
+            if values.len() != epochs.len() + 3 {
+                return Err(parse_error(line_number, "wrong number of columns"));
+            }
+            let (n, m) = (values[0], values[1]);
+            if n < 1.0 || m < 0.0 || m > n || n.fract() != 0.0 || m.fract() != 0.0 {
+                return Err(parse_error(line_number, "invalid degree or order"));
+            }
+            // Whole numbers checked above, degrees in published tables are at most 13
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let (n, m) = (n as usize, m as usize);
+            degree = degree.max(n);
+            let (per_epoch, last) = values[2..].split_at(epochs.len());
+            indexed.push((is_g, n, m, per_epoch.to_vec(), last[0]));
+        }
+        if indexed.is_empty() {
+            return Err(Error::CouldntParseGeomagneticModel(
+                "no coefficients".to_string(),
+            ));
+        }
+
+        let mut coefficients = vec![Coefficients::zero(degree); epochs.len()];
+        let mut secular_variation = Coefficients::zero(degree);
+        for (is_g, n, m, per_epoch, last) in indexed {
+            for (epoch, value) in coefficients.iter_mut().zip(per_epoch) {
+                let target = if is_g { &mut epoch.g } else { &mut epoch.h };
+                target[n][m] = value;
+            }
+            let target = if is_g {
+                &mut secular_variation.g
+            } else {
+                &mut secular_variation.h
+            };
+            target[n][m] = last;
+        }
+        Ok(Self {
+            epochs,
+            coefficients,
+            secular_variation,
+        })
+    }
+
+    /// Years the model covers, from its first epoch to five years past its last
+    #[must_use]
+    pub fn valid_years(&self) -> (f64, f64) {
+        (
+            self.epochs[0],
+            self.epochs[self.epochs.len() - 1] + EXTRAPOLATION_YEARS,
+        )
+    }
+
+    fn coefficients_at(&self, year: f64) -> Option<Coefficients> {
+        let (first, last) = self.valid_years();
+        if !(first..=last).contains(&year) {
+            return None;
+        }
+        let next = self.epochs.iter().position(|&epoch| epoch > year);
+        Some(match next {
+            Some(next) => {
+                let previous = next - 1;
+                let fraction =
+                    (year - self.epochs[previous]) / (self.epochs[next] - self.epochs[previous]);
+                self.coefficients[previous].interpolate(&self.coefficients[next], fraction)
+            }
+            None => self.coefficients[self.coefficients.len() - 1].add_scaled(
+                &self.secular_variation,
+                year - self.epochs[self.epochs.len() - 1],
+            ),
+        })
+    }
+
+    /// Compute the magnetic declination at a place and date
+    /// # Arguments
+    /// - `date` - Date of the survey
+    /// - `location` - Latitude and longitude on WGS84, with the elevation taken as the height above the ellipsoid
+    /// # Returns
+    /// Declination in degrees, positive when magnetic north is east of true north,
+    /// `None` if the date is outside the years the model covers
+    #[must_use]
+    pub fn declination(&self, date: Date, location: GeographicLocation) -> Option<f64> {
+        let coefficients = self.coefficients_at(decimal_year(date))?;
+        let (north, east) = field(&coefficients, location);
+        Some(east.atan2(north) * 180.0 / PI)
+    }
+}
+
+/// North and east components of the field in nanotesla, in the local geodetic frame
+fn field(coefficients: &Coefficients, location: GeographicLocation) -> (f64, f64) {
+    let height = location.elevation / 1000.0;
+    let (sin_latitude, cos_latitude) = location.latitude.to_radians().sin_cos();
+    let (a2, b2) = (SEMI_MAJOR_AXIS.powi(2), SEMI_MINOR_AXIS.powi(2));
+    let rho_squared = a2 * cos_latitude.powi(2) + b2 * sin_latitude.powi(2);
+    let rho = rho_squared.sqrt();
+    let radius = (height * (height + 2.0 * rho)
+        + (a2 * a2 * cos_latitude.powi(2) + b2 * b2 * sin_latitude.powi(2)) / rho_squared)
+        .sqrt();
+    // Rotation between the geodetic and geocentric verticals
+    let cos_delta = (height + rho) / radius;
+    let sin_delta = (a2 - b2) / rho * cos_latitude * sin_latitude / radius;
+    let cos_theta = sin_latitude * cos_delta - cos_latitude * sin_delta;
+    // Keep away from the poles, where the east component divides by zero
+    let sin_theta = (cos_latitude * cos_delta + sin_latitude * sin_delta).max(1e-10);
+    let phi = location.longitude.to_radians();
+
+    let degree = coefficients.g.len() - 1;
+    let (functions, derivatives) = legendre(degree, cos_theta, sin_theta);
+    // Field components along geocentric north, east and down
+    let (mut north, mut east, mut down) = (0.0, 0.0, 0.0);
+    let ratio = REFERENCE_RADIUS / radius;
+    for n in 1..=degree {
+        let scale = ratio.powi(i32::try_from(n + 2).unwrap_or(i32::MAX));
+        for m in 0..=n {
+            let (sin_m_phi, cos_m_phi) = (float(m) * phi).sin_cos();
+            let (g, h) = (coefficients.g[n][m], coefficients.h[n][m]);
+            let along = g * cos_m_phi + h * sin_m_phi;
+            north += scale * along * derivatives[n][m];
+            east +=
+                scale * float(m) * (g * sin_m_phi - h * cos_m_phi) * functions[n][m] / sin_theta;
+            down -= scale * (float(n) + 1.0) * along * functions[n][m];
+        }
+    }
+    (north * cos_delta + down * sin_delta, east)
+}
+
+/// A survey whose recorded declination disagrees with the geomagnetic model
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeclinationDiscrepancy<'a> {
+    pub survey: &'a Survey,
+    /// Declination in the survey's header, in degrees
+    pub recorded: f64,
+    /// Declination from the model for the survey's date at the base location, in degrees
+    pub modelled: f64,
+}
+
+impl DeclinationDiscrepancy<'_> {
+    /// Recorded minus modelled declination, in degrees
+    #[must_use]
+    pub fn difference(&self) -> f64 {
+        self.recorded - self.modelled
+    }
+}
+
+impl Project<Loaded> {
+    /// Compute the declination at the project's base location on a date
+    /// # Returns
+    /// Declination in degrees, `None` if the date is outside the years the model covers
+    #[must_use]
+    pub fn modelled_declination(&self, model: &GeomagneticModel, date: Date) -> Option<f64> {
+        model.declination(date, self.geomagnetic_location())
+    }
+
+    /// The base location on WGS84, which the model's coordinates are on
+    fn geomagnetic_location(&self) -> GeographicLocation {
+        self.base_location
+            .to_geographic(self.datum)
+            .to_datum(self.datum, Datum::Wgs1984)
+    }
+
+    /// Find the surveys whose recorded declination differs from the model by more than a tolerance
+    /// Surveys dated outside the years the model covers are skipped.
+    /// # Arguments
+    /// - `model` - Geomagnetic model to compare against
+    /// - `tolerance` - Largest acceptable difference, in degrees
+    /// # Returns
+    /// The discrepancies, in project order
+    #[must_use]
+    pub fn declination_discrepancies(
+        &self,
+        model: &GeomagneticModel,
+        tolerance: f64,
+    ) -> Vec<DeclinationDiscrepancy<'_>> {
+        self.surveys()
+            .filter_map(|(_, survey)| {
+                let modelled = self.modelled_declination(model, survey.date)?;
+                let discrepancy = DeclinationDiscrepancy {
+                    survey,
+                    recorded: survey.parameters.declination,
+                    modelled,
+                };
+                (discrepancy.difference().abs() > tolerance).then_some(discrepancy)
+            })
+            .collect()
+    }
+
+    /// Replace recorded declinations that differ from the model by more than a tolerance with the modelled value
+    /// Modelled values are rounded to the hundredth of a degree Compass stores.
+    /// # Arguments
+    /// - `model` - Geomagnetic model to take declinations from
+    /// - `tolerance` - Largest acceptable difference, in degrees, `0.0` replaces every declination the model covers
+    /// # Returns
+    /// The number of surveys changed
+    pub fn fill_declinations(&mut self, model: &GeomagneticModel, tolerance: f64) -> usize {
+        let location = self.geomagnetic_location();
+        let mut changed = 0;
+        for survey in self
+            .survey_files
+            .iter_mut()
+            .flat_map(SurveyFile::surveys_mut)
+        {
+            let Some(modelled) = model.declination(survey.date, location) else {
+                continue;
+            };
+            let modelled = (modelled * 100.0).round() / 100.0;
+            if (survey.parameters.declination - modelled).abs() > tolerance {
+                survey.parameters.declination = modelled;
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{Parameters, SurveyFile, UtmLocation};
+
+    /// The dipole terms of IGRF-14, laid out as IAGA publishes the tables
+    const DIPOLE: &str = "# Test table, dipole terms only
+c/s deg ord DGRF IGRF SV
+g/h n m 2020.0 2025.0 2025-30
+g 1 0 -29403.41 -29350.0 12.6
+g 1 1 -1451.37 -1410.3 10.0
+h 1 1 4653.35 4545.5 -21.5
+";
+
+    fn location(latitude: f64, longitude: f64) -> GeographicLocation {
+        GeographicLocation {
+            latitude,
+            longitude,
+            elevation: 0.0,
+        }
+    }
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { month, day, year }
+    }
+
+    #[test]
+    fn decimal_years() {
+        assert_float_eq!(
+            decimal_year(date(2021, 1, 1)),
+            2021.0 + 0.5 / 365.0,
+            abs <= 1e-9
+        );
+        assert_float_eq!(
+            decimal_year(date(2020, 12, 31)),
+            2021.0 - 0.5 / 366.0,
+            abs <= 1e-9
+        );
+        assert_float_eq!(
+            decimal_year(date(79, 7, 10)),
+            decimal_year(date(1979, 7, 10)),
+            abs <= f64::EPSILON
+        );
+    }
+
+    #[test]
+    fn parse_table() {
+        let model = GeomagneticModel::parse(DIPOLE).unwrap();
+        assert_eq!(model.valid_years(), (2020.0, 2030.0));
+        assert_float_eq!(model.coefficients[1].h[1][1], 4545.5, abs <= f64::EPSILON);
+        assert_float_eq!(model.secular_variation.g[1][0], 12.6, abs <= f64::EPSILON);
+
+        assert!(matches!(
+            GeomagneticModel::parse("g 1 0 1.0 2.0"),
+            Err(Error::CouldntParseGeomagneticModel(_))
+        ));
+        assert!(matches!(
+            GeomagneticModel::parse("g/h n m 2015.0 2020.0 2020-25\ng 1 0 1.0 2.0"),
+            Err(Error::CouldntParseGeomagneticModel(message)) if message.starts_with("line 2")
+        ));
+    }
+
+    #[test]
+    fn dipole_declination() {
+        let model = GeomagneticModel::parse(DIPOLE).unwrap();
+        // On the equator at the prime meridian a dipole's field points north -g10 and east -h11,
+        // weakened by the cube of the distance past the reference radius
+        let coefficients = model.coefficients_at(2025.0).unwrap();
+        let (north, east) = field(&coefficients, location(0.0, 0.0));
+        let scale = (REFERENCE_RADIUS / SEMI_MAJOR_AXIS).powi(3);
+        assert_float_eq!(north, 29350.0 * scale, abs <= 1e-6);
+        assert_float_eq!(east, -4545.5 * scale, abs <= 1e-6);
+        let declination = model
+            .declination(date(2025, 1, 1), location(0.0, 0.0))
+            .unwrap();
+        assert_float_eq!(
+            declination,
+            (-4545.5f64).atan2(29350.0).to_degrees(),
+            abs <= 0.01
+        );
+
+        // Between epochs the coefficients are interpolated, after the last they are extrapolated
+        let middle = model.coefficients_at(2022.5).unwrap();
+        assert_float_eq!(middle.g[1][0], (-29403.41 - 29350.0) / 2.0, abs <= 1e-9);
+        let later = model.coefficients_at(2027.0).unwrap();
+        assert_float_eq!(later.h[1][1], 4545.5 - 2.0 * 21.5, abs <= 1e-9);
+        assert_eq!(
+            model.declination(date(2015, 6, 1), location(0.0, 0.0)),
+            None
+        );
+        assert_eq!(
+            model.declination(date(2031, 6, 1), location(0.0, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn igrf_declinations() {
+        let model = GeomagneticModel::igrf();
+        assert_eq!(model.valid_years(), (1900.0, 2030.0));
+        // Published declinations for these places and dates, to a few tenths of a degree
+        for (latitude, longitude, year, published) in [
+            // London, where the agonic line crossed in 2019 and 2020
+            (51.5, -0.13, 2020, 0.0),
+            (51.5, -0.13, 1985, -5.2),
+            (51.5, -0.13, 1900, -16.5),
+            (51.5, -0.13, 2026, 1.1),
+            // Boulder, Colorado
+            (40.01, -105.27, 2020, 8.2),
+            (40.01, -105.27, 1990, 11.3),
+            (40.01, -105.27, 2026, 7.7),
+            // Washington, D.C.
+            (38.9, -77.04, 2020, -10.8),
+            // Sydney
+            (-33.87, 151.21, 2020, 12.6),
+            // Reykjavik
+            (64.15, -21.94, 2000, -18.9),
+        ] {
+            let declination = model
+                .declination(date(year, 1, 1), location(latitude, longitude))
+                .unwrap();
+            assert_float_eq!(declination, published, abs <= 0.3);
+        }
+        assert_eq!(
+            model.declination(date(1899, 12, 31), location(51.5, -0.13)),
+            None
+        );
+        assert_eq!(
+            model.declination(date(2031, 1, 1), location(51.5, -0.13)),
+            None
+        );
+    }
+
+    #[test]
+    fn field_matches_potential() {
+        // The field components are derivatives of the potential, so check them by finite differences
+        let mut coefficients = Coefficients::zero(4);
+        for n in 1..=4 {
+            for m in 0..=n {
+                coefficients.g[n][m] = 1000.0 / float(n + m + 1);
+                coefficients.h[n][m] = if m == 0 { 0.0 } else { -700.0 / float(n * m) };
+            }
+        }
+        // On the equator the geodetic and geocentric frames agree, and the radius is the semi-major axis
+        let ratio = REFERENCE_RADIUS / SEMI_MAJOR_AXIS;
+        let potential = |theta: f64, phi: f64| {
+            let (p, _) = legendre(4, theta.cos(), theta.sin());
+            let mut v = 0.0;
+            for (n, row) in p.iter().enumerate().skip(1) {
+                let scale = ratio.powi(i32::try_from(n + 2).unwrap());
+                for (m, value) in row.iter().enumerate() {
+                    let angle = float(m) * phi;
+                    v += scale
+                        * (coefficients.g[n][m] * angle.cos() + coefficients.h[n][m] * angle.sin())
+                        * value;
+                }
+            }
+            v
+        };
+        let (theta, phi, step) = (PI / 2.0, 0.7, 1e-6);
+        let north = (potential(theta + step, phi) - potential(theta - step, phi)) / (2.0 * step);
+        let east = -(potential(theta, phi + step) - potential(theta, phi - step)) / (2.0 * step);
+        let (x, y) = field(&coefficients, location(0.0, phi.to_degrees()));
+        assert_float_eq!(x, north, abs <= 1e-3);
+        assert_float_eq!(y, east, abs <= 1e-3);
+    }
+
+    #[test]
+    fn check_and_fill_project_declinations() {
+        let model = GeomagneticModel::parse(DIPOLE).unwrap();
+        let base_location =
+            UtmLocation::from_geographic(location(39.5, -106.6), Datum::NorthAmerican1983, None);
+        let mut project = Project::new("decl.mak", base_location, Datum::NorthAmerican1983, None);
+        let modelled = project
+            .modelled_declination(&model, date(2024, 7, 4))
+            .unwrap();
+        assert!(modelled > 0.0);
+
+        let survey_file = project.add_survey_file(SurveyFile::new("decl.dat"));
+        for (name, year, declination) in [
+            ("A", 2022, 0.0),
+            ("B", 2024, modelled + 0.2),
+            ("C", 1990, 0.0),
+        ] {
+            survey_file.add_survey(Survey::new(
+                "Test Cave",
+                name,
+                date(year, 7, 4),
+                "",
+                Parameters {
+                    declination,
+                    format: None,
+                    correction_factors: None,
+                    backsight_correction_factors: None,
+                },
+            ));
+        }
+
+        // B is within tolerance, and C predates the model
+        let report = project.declination_discrepancies(&model, 0.5);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].survey.name, "A");
+        assert_float_eq!(report[0].difference(), -report[0].modelled, abs <= 1e-9);
+        let filled = (report[0].modelled * 100.0).round() / 100.0;
+
+        assert_eq!(project.fill_declinations(&model, 0.5), 1);
+        assert!(project.declination_discrepancies(&model, 0.5).is_empty());
+        assert_float_eq!(
+            project.survey("A").unwrap().1.parameters.declination,
+            filled,
+            abs <= 1e-9
+        );
+        assert_float_eq!(
+            project.survey("B").unwrap().1.parameters.declination,
+            modelled + 0.2,
+            abs <= 1e-9
+        );
+        assert_float_eq!(
+            project.survey("C").unwrap().1.parameters.declination,
+            0.0,
+            abs <= f64::EPSILON
+        );
+    }
+}
@@ -1,13 +1,36 @@
 //! [![Static Badge](https://img.shields.io/badge/GitHub-gray?style=for-the-badge&logo=GitHub)](https://github.com/zheylmun/compass_data)
 mod common_types;
 mod error;
+mod extended_elevation;
+mod geodesy;
+mod geomagnetism;
+mod network;
 mod parser_utils;
+mod passage;
 mod project;
+mod reduction;
+mod statistics;
 mod survey;
-pub use common_types::{EastNorthElevation, UtmLocation};
+#[cfg(test)]
+mod test_utils;
+pub use common_types::{Date, EastNorthElevation, Hemisphere, UtmLocation};
 pub use error::Error;
-pub use project::{Datum, Project, SurveyFile};
-pub use survey::{BackSightCorrectionFactors, CorrectionFactors, Parameters, Shot, Survey};
+pub use extended_elevation::{ExtendedElevation, ExtendedElevationOptions, ProfilePoint};
+pub use geodesy::GeographicLocation;
+pub use geomagnetism::{DeclinationDiscrepancy, GeomagneticModel};
+pub use network::{NetworkPath, ShotNetwork, UnconnectedLinks};
+pub use passage::{CrossSection, PassageMesh, PassageOutline};
+pub use project::{
+    Datum, DeclinationMode, FixUnits, Project, ProjectFolder, ProjectParameters, ProjectShot,
+    Station, StationFix, SurveyFile,
+};
+pub use reduction::{AdjustedCoordinates, BacksightDiscrepancy, BacksightTolerances, LoopClosure};
+pub use statistics::Statistics;
+pub use survey::{
+    BackSightCorrectionFactors, BacksightMode, BearingUnits, CorrectionFactors, InclinationUnits,
+    LengthUnits, LineEnding, LrudAssociation, LrudItem, Measurement, Parameters, Shot, ShotFlags,
+    ShotItem, Survey, SurveyFormat,
+};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,523 @@
+//! Shot network
+//!
+//! A graph over survey data, with stations as nodes and shots as edges.
+//! Used to find how the parts of a cave connect: separate components, dead ends and junctions,
+//! surveys and survey files which don't tie in to the rest of the data, and the shortest route between stations.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    ptr,
+};
+
+use crate::{
+    common_types::FEET_TO_METERS, project::Loaded, reduction::Corrections, Error, Project, Shot,
+    Station, Survey, SurveyFile,
+};
+
+/// A shot joining two stations of the network
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    from: usize,
+    to: usize,
+    /// Index into the network's surveys
+    survey: usize,
+    /// Index into the network's shots
+    shot: usize,
+}
+
+/// Stations and the shots between them
+/// Every shot is an edge, except shots flagged X when shot flags are applied,
+/// which are left out along with any stations only they reach.
+#[derive(Clone, Debug)]
+pub struct ShotNetwork<'a> {
+    /// Station names, in the order they first appear in the data
+    stations: Vec<&'a str>,
+    indices: HashMap<&'a str, usize>,
+    surveys: Vec<(Option<&'a SurveyFile<Loaded>>, &'a Survey)>,
+    shots: Vec<&'a Shot>,
+    edges: Vec<Edge>,
+    /// Edges at each station, shots from a station to itself aren't included
+    adjacency: Vec<Vec<usize>>,
+    /// The component each station belongs to, numbered in order of first appearance
+    components: Vec<usize>,
+}
+
+/// The shortest route through the network between two stations
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkPath<'a> {
+    /// Stations along the route, including both ends
+    pub stations: Vec<&'a str>,
+    /// Shots along the route, in order
+    pub shots: Vec<&'a Shot>,
+    /// Total tape length in meters
+    pub length: f64,
+}
+
+/// A survey file whose link stations don't connect it to any other file
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnconnectedLinks<'a> {
+    pub survey_file: &'a SurveyFile<Loaded>,
+    /// Link stations missing from the survey data, or only reached by this file's own shots
+    pub stations: Vec<&'a Station>,
+}
+
+/// Entry in the priority queue of the shortest path search, the nearest station comes out first
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Candidate {
+    distance: f64,
+    station: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> ShotNetwork<'a> {
+    /// Build the network from surveys, along with the corrections which say whether their X flags apply
+    fn new(surveys: Vec<(Option<&'a SurveyFile<Loaded>>, &'a Survey, Corrections)>) -> Self {
+        let mut network = Self {
+            stations: Vec::new(),
+            indices: HashMap::new(),
+            surveys: Vec::with_capacity(surveys.len()),
+            shots: Vec::new(),
+            edges: Vec::new(),
+            adjacency: Vec::new(),
+            components: Vec::new(),
+        };
+        for (survey_file, data, corrections) in surveys {
+            let survey = network.surveys.len();
+            network.surveys.push((survey_file, data));
+            for shot in data.shots.iter().filter(|shot| !corrections.excludes(shot)) {
+                let from = network.add_station(&shot.from);
+                let to = network.add_station(&shot.to);
+                let edge = network.edges.len();
+                network.edges.push(Edge {
+                    from,
+                    to,
+                    survey,
+                    shot: network.shots.len(),
+                });
+                network.shots.push(shot);
+                if from != to {
+                    network.adjacency[from].push(edge);
+                    network.adjacency[to].push(edge);
+                }
+            }
+        }
+        network.label_components();
+        network
+    }
+
+    fn add_station(&mut self, name: &'a str) -> usize {
+        *self.indices.entry(name).or_insert_with(|| {
+            self.stations.push(name);
+            self.adjacency.push(Vec::new());
+            self.stations.len() - 1
+        })
+    }
+
+    fn label_components(&mut self) {
+        let mut components = vec![usize::MAX; self.stations.len()];
+        let mut count = 0;
+        for start in 0..self.stations.len() {
+            if components[start] != usize::MAX {
+                continue;
+            }
+            components[start] = count;
+            let mut stack = vec![start];
+            while let Some(station) = stack.pop() {
+                for neighbour in self.neighbour_indices(station) {
+                    if components[neighbour] == usize::MAX {
+                        components[neighbour] = count;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            count += 1;
+        }
+        self.components = components;
+    }
+
+    fn neighbour_indices(&self, station: usize) -> impl Iterator<Item = usize> + '_ {
+        self.adjacency[station].iter().map(move |&edge| {
+            let Edge { from, to, .. } = self.edges[edge];
+            if from == station {
+                to
+            } else {
+                from
+            }
+        })
+    }
+
+    fn index(&self, station: &str) -> Result<usize, Error> {
+        self.indices
+            .get(station)
+            .copied()
+            .ok_or_else(|| Error::StationNotFound(station.to_string()))
+    }
+
+    /// Number of other stations a station is joined to, repeated shots between the same stations count once
+    fn degree(&self, station: usize) -> usize {
+        self.neighbour_indices(station)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Every station in the network, in the order they first appear in the data
+    pub fn stations(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.stations.iter().copied()
+    }
+
+    /// Whether any shot in the network starts or ends at a station
+    #[must_use]
+    pub fn contains(&self, station: &str) -> bool {
+        self.indices.contains_key(station)
+    }
+
+    /// The stations joined to a station by a shot, in the order of the shots
+    /// # Errors
+    /// - [`Error::StationNotFound`] If no shot in the network touches the station
+    pub fn neighbours(&self, station: &str) -> Result<Vec<&'a str>, Error> {
+        let mut neighbours = Vec::new();
+        for neighbour in self.neighbour_indices(self.index(station)?) {
+            if !neighbours.contains(&self.stations[neighbour]) {
+                neighbours.push(self.stations[neighbour]);
+            }
+        }
+        Ok(neighbours)
+    }
+
+    /// Groups of stations connected to each other by shots
+    /// # Returns
+    /// One list of stations per group, in the order their first station appears in the data
+    #[must_use]
+    pub fn components(&self) -> Vec<Vec<&'a str>> {
+        let mut components: Vec<Vec<&str>> = Vec::new();
+        for (station, &component) in self.stations.iter().zip(&self.components) {
+            if component == components.len() {
+                components.push(Vec::new());
+            }
+            components[component].push(station);
+        }
+        components
+    }
+
+    /// Whether two stations are connected by any route through the network
+    /// # Errors
+    /// - [`Error::StationNotFound`] If either station isn't in the network
+    pub fn connected(&self, from: &str, to: &str) -> Result<bool, Error> {
+        Ok(self.components[self.index(from)?] == self.components[self.index(to)?])
+    }
+
+    /// Stations joined to exactly one other station
+    #[must_use]
+    pub fn dead_ends(&self) -> Vec<&'a str> {
+        (0..self.stations.len())
+            .filter(|&station| self.degree(station) == 1)
+            .map(|station| self.stations[station])
+            .collect()
+    }
+
+    /// Stations where three or more other stations meet
+    #[must_use]
+    pub fn junctions(&self) -> Vec<&'a str> {
+        (0..self.stations.len())
+            .filter(|&station| self.degree(station) > 2)
+            .map(|station| self.stations[station])
+            .collect()
+    }
+
+    /// Surveys which aren't connected to any other survey, directly or through other surveys
+    /// A network of a single survey returns that survey.
+    #[must_use]
+    pub fn isolated_surveys(&self) -> Vec<&'a Survey> {
+        let mut surveys_in_component: Vec<HashSet<usize>> =
+            vec![HashSet::new(); self.stations.len()];
+        for edge in &self.edges {
+            surveys_in_component[self.components[edge.from]].insert(edge.survey);
+        }
+        self.surveys
+            .iter()
+            .enumerate()
+            .filter(|&(survey, (_, data))| {
+                !data.shots.is_empty()
+                    && self
+                        .edges
+                        .iter()
+                        .filter(|edge| edge.survey == survey)
+                        .all(|edge| surveys_in_component[self.components[edge.from]].len() == 1)
+            })
+            .map(|(_, (_, data))| *data)
+            .collect()
+    }
+
+    /// Find the route between two stations with the least total tape length
+    /// # Returns
+    /// The route, `None` if the stations aren't connected
+    /// # Errors
+    /// - [`Error::StationNotFound`] If either station isn't in the network
+    pub fn shortest_path(&self, from: &str, to: &str) -> Result<Option<NetworkPath<'a>>, Error> {
+        let (start, end) = (self.index(from)?, self.index(to)?);
+        let mut distances = vec![f64::INFINITY; self.stations.len()];
+        let mut reached_by: Vec<Option<usize>> = vec![None; self.stations.len()];
+        let mut queue = BinaryHeap::new();
+        distances[start] = 0.0;
+        queue.push(Candidate {
+            distance: 0.0,
+            station: start,
+        });
+        while let Some(Candidate { distance, station }) = queue.pop() {
+            if station == end {
+                break;
+            }
+            if distance > distances[station] {
+                continue;
+            }
+            for &edge in &self.adjacency[station] {
+                let Edge { from, to, shot, .. } = self.edges[edge];
+                let neighbour = if from == station { to } else { from };
+                let distance = distance + self.shots[shot].length * FEET_TO_METERS;
+                if distance < distances[neighbour] {
+                    distances[neighbour] = distance;
+                    reached_by[neighbour] = Some(edge);
+                    queue.push(Candidate {
+                        distance,
+                        station: neighbour,
+                    });
+                }
+            }
+        }
+        if distances[end].is_infinite() {
+            return Ok(None);
+        }
+
+        let mut stations = vec![self.stations[end]];
+        let mut shots = Vec::new();
+        let mut station = end;
+        while let Some(edge) = reached_by[station] {
+            let Edge { from, to, shot, .. } = self.edges[edge];
+            station = if from == station { to } else { from };
+            stations.push(self.stations[station]);
+            shots.push(self.shots[shot]);
+        }
+        stations.reverse();
+        shots.reverse();
+        Ok(Some(NetworkPath {
+            stations,
+            shots,
+            length: distances[end],
+        }))
+    }
+}
+
+impl Survey {
+    /// The network of the survey's stations and shots, leaving out shots flagged X
+    #[must_use]
+    pub fn network(&self) -> ShotNetwork<'_> {
+        ShotNetwork::new(vec![(None, self, Corrections::SURVEY)])
+    }
+}
+
+impl Project<Loaded> {
+    /// The network of every station and shot in the project
+    /// Shots flagged X are left out unless the project or file parameters turn shot flags off.
+    #[must_use]
+    pub fn network(&self) -> ShotNetwork<'_> {
+        ShotNetwork::new(
+            self.surveys()
+                .map(|(survey_file, survey)| {
+                    let corrections = Corrections::for_file(self, survey_file);
+                    (Some(survey_file), survey, corrections)
+                })
+                .collect(),
+        )
+    }
+
+    /// Find survey files whose link stations don't actually tie them to the rest of the project
+    /// A link station connects when the shots around it lead to another file's shots.
+    /// # Returns
+    /// Each file with link stations that don't connect, in project order
+    #[must_use]
+    pub fn unconnected_link_stations(&self) -> Vec<UnconnectedLinks<'_>> {
+        let network = self.network();
+        // The files with shots in each component
+        let component_count = network.components.iter().max().map_or(0, |last| last + 1);
+        let mut files_in_component: Vec<Vec<&SurveyFile<Loaded>>> =
+            vec![Vec::new(); component_count];
+        for edge in &network.edges {
+            let files = &mut files_in_component[network.components[edge.from]];
+            if let (Some(survey_file), _) = network.surveys[edge.survey] {
+                if !files.iter().any(|file| ptr::eq(*file, survey_file)) {
+                    files.push(survey_file);
+                }
+            }
+        }
+        self.survey_files
+            .iter()
+            .filter_map(|survey_file| {
+                let stations: Vec<&Station> = survey_file
+                    .link_stations()
+                    .filter(|station| {
+                        network.indices.get(station.name()).is_none_or(|&index| {
+                            files_in_component[network.components[index]]
+                                .iter()
+                                .all(|file| ptr::eq(*file, survey_file))
+                        })
+                    })
+                    .collect();
+                (!stations.is_empty()).then_some(UnconnectedLinks {
+                    survey_file,
+                    stations,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{
+        test_utils::survey, Datum, EastNorthElevation, Hemisphere, ProjectParameters, ShotFlags,
+        UtmLocation,
+    };
+
+    fn shot(from: &str, to: &str, length: f64) -> Shot {
+        Shot::new(from, to, length, 0.0, 0.0)
+    }
+
+    fn excluded_shot(from: &str, to: &str, length: f64) -> Shot {
+        let mut shot = shot(from, to, length);
+        shot.flags = Some(ShotFlags {
+            exclude_from_processing: true,
+            ..ShotFlags::default()
+        });
+        shot
+    }
+
+    #[test]
+    fn survey_network() {
+        // A loop A1-A2-A3-A4 with a long direct shot A1-A4, a side passage at A2 and a repeated shot
+        let survey = survey(
+            "A",
+            vec![
+                shot("A1", "A2", 10.0),
+                shot("A2", "A3", 10.0),
+                shot("A3", "A4", 10.0),
+                shot("A1", "A4", 50.0),
+                shot("A2", "A5", 5.0),
+                shot("A2", "A5", 5.0),
+                shot("B1", "B2", 5.0),
+                // Left out of processing, so neither a route to B1 nor a way to reach C1
+                excluded_shot("A5", "B1", 1.0),
+                excluded_shot("A5", "C1", 1.0),
+            ],
+        );
+        let network = survey.network();
+        assert_eq!(network.stations().count(), 7);
+        assert!(!network.contains("C1"));
+        assert_eq!(
+            network.components(),
+            vec![vec!["A1", "A2", "A3", "A4", "A5"], vec!["B1", "B2"]]
+        );
+        assert_eq!(network.junctions(), vec!["A2"]);
+        assert_eq!(network.dead_ends(), vec!["A5", "B1", "B2"]);
+        assert_eq!(network.neighbours("A2").unwrap(), vec!["A1", "A3", "A5"]);
+        assert!(network.connected("A5", "A4").unwrap());
+        assert!(!network.connected("A5", "B1").unwrap());
+        assert!(matches!(
+            network.neighbours("Z9"),
+            Err(Error::StationNotFound(station)) if station == "Z9"
+        ));
+        assert_eq!(network.isolated_surveys(), vec![&survey]);
+
+        let path = network.shortest_path("A4", "A5").unwrap().unwrap();
+        assert_eq!(path.stations, vec!["A4", "A3", "A2", "A5"]);
+        assert_eq!(path.shots.len(), 3);
+        assert_float_eq!(path.length, 25.0 * FEET_TO_METERS, abs <= 1e-9);
+        let path = network.shortest_path("A1", "A1").unwrap().unwrap();
+        assert_eq!(path.stations, vec!["A1"]);
+        assert!(network.shortest_path("A1", "B2").unwrap().is_none());
+    }
+
+    #[test]
+    fn project_links() {
+        let base_location = UtmLocation {
+            east_north_elevation: EastNorthElevation::from_meters(
+                357_715.717,
+                4_372_837.574,
+                3048.0,
+            ),
+            zone: 13,
+            hemisphere: Hemisphere::North,
+            convergence_angle: 0.0,
+        };
+        let mut project = Project::new("links.mak", base_location, Datum::NorthAmerican1983, None);
+        let entrance = project.add_survey_file(SurveyFile::new("entrance.dat"));
+        entrance.add_survey(survey(
+            "E",
+            vec![shot("E1", "E2", 10.0), shot("E2", "J1", 10.0)],
+        ));
+        entrance.add_link_station("J1");
+        let lower = project.add_survey_file(SurveyFile::new("lower.dat"));
+        lower.add_survey(survey("L", vec![shot("J1", "L1", 10.0)]));
+        lower.add_link_station("J1");
+        // Linked by a station which was never surveyed to
+        let upper = project.add_survey_file(SurveyFile::new("upper.dat"));
+        upper.add_survey(survey("U", vec![shot("U1", "U2", 10.0)]));
+        upper.add_link_station("U1");
+        upper.add_link_station("E9");
+
+        let network = project.network();
+        assert_eq!(network.components().len(), 2);
+        let isolated: Vec<&str> = network
+            .isolated_surveys()
+            .iter()
+            .map(|survey| survey.name.as_str())
+            .collect();
+        assert_eq!(isolated, vec!["U"]);
+        let path = network.shortest_path("E1", "L1").unwrap().unwrap();
+        assert_eq!(path.stations, vec!["E1", "E2", "J1", "L1"]);
+
+        let unconnected = project.unconnected_link_stations();
+        assert_eq!(unconnected.len(), 1);
+        assert_eq!(
+            unconnected[0].survey_file.file_path,
+            std::path::PathBuf::from("upper.dat")
+        );
+        let stations: Vec<&str> = unconnected[0]
+            .stations
+            .iter()
+            .map(|station| station.name())
+            .collect();
+        assert_eq!(stations, vec!["U1", "E9"]);
+
+        // A shot flagged X doesn't tie the upper file in, unless shot flags are turned off
+        project.survey_files[2].surveys_mut()[0]
+            .shots
+            .push(excluded_shot("U2", "J1", 10.0));
+        assert_eq!(project.network().components().len(), 2);
+        assert_eq!(project.unconnected_link_stations()[0].stations.len(), 2);
+        project.parameters = Some(ProjectParameters {
+            apply_shot_flags: Some(false),
+            ..ProjectParameters::default()
+        });
+        assert_eq!(project.network().components().len(), 1);
+        let unconnected = project.unconnected_link_stations();
+        assert_eq!(unconnected.len(), 1);
+        assert_eq!(unconnected[0].stations[0].name(), "E9");
+    }
+}
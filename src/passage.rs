@@ -0,0 +1,540 @@
+//! Passage geometry
+//!
+//! Turns the left, right, up and down passage dimensions recorded with each shot into walls around the centerline:
+//! a cross-section at each station, plan view outlines of the passage, and a closed tube for 3D display.
+//! Survey data stores dimensions in feet, the geometry is always in meters.
+
+use std::collections::HashMap;
+
+use crate::{
+    common_types::FEET_TO_METERS, project::Loaded, EastNorthElevation, LrudAssociation,
+    Measurement, Project, Shot, Survey, SurveyFormat,
+};
+
+/// Shots shorter than this horizontally, in meters, have no direction of their own to face the cross-section along
+const MIN_HORIZONTAL_LENGTH: f64 = 1e-6;
+
+/// The passage dimensions measured at a station, facing along the shot they were recorded with
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossSection {
+    pub station: String,
+    /// Location of the station in meters
+    pub location: EastNorthElevation,
+    /// Azimuth the cross-section faces along, in degrees from grid north
+    pub facing: f64,
+    /// Distances to the walls, ceiling and floor in meters, `None` where no dimension was measured
+    pub left: Option<f64>,
+    pub right: Option<f64>,
+    pub up: Option<f64>,
+    pub down: Option<f64>,
+}
+
+impl CrossSection {
+    /// Horizontal offset of one meter to the right of the facing direction
+    fn right_unit(&self) -> EastNorthElevation {
+        let (sin, cos) = (self.facing + 90.0).to_radians().sin_cos();
+        EastNorthElevation::from_meters(sin, cos, 0.0)
+    }
+
+    fn offset(&self, direction: EastNorthElevation, distance: f64) -> EastNorthElevation {
+        self.location
+            + EastNorthElevation::from_meters(
+                direction.easting * distance,
+                direction.northing * distance,
+                direction.up * distance,
+            )
+    }
+
+    /// Location of the left wall, level with the station
+    #[must_use]
+    pub fn left_wall(&self) -> Option<EastNorthElevation> {
+        Some(self.offset(-self.right_unit(), self.left?))
+    }
+
+    /// Location of the right wall, level with the station
+    #[must_use]
+    pub fn right_wall(&self) -> Option<EastNorthElevation> {
+        Some(self.offset(self.right_unit(), self.right?))
+    }
+
+    /// Location of the ceiling, above the station
+    #[must_use]
+    pub fn ceiling(&self) -> Option<EastNorthElevation> {
+        Some(self.location + EastNorthElevation::from_meters(0.0, 0.0, self.up?))
+    }
+
+    /// Location of the floor, below the station
+    #[must_use]
+    pub fn floor(&self) -> Option<EastNorthElevation> {
+        Some(self.location - EastNorthElevation::from_meters(0.0, 0.0, self.down?))
+    }
+
+    /// The left, ceiling, right and floor points in order, with missing dimensions collapsed onto the station
+    fn ring(&self) -> [EastNorthElevation; 4] {
+        [
+            self.left_wall(),
+            self.ceiling(),
+            self.right_wall(),
+            self.floor(),
+        ]
+        .map(|point| point.unwrap_or(self.location))
+    }
+}
+
+/// Plan view outline of a continuous stretch of passage
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassageOutline {
+    /// Stations along the stretch with a cross-section, in survey order
+    pub stations: Vec<String>,
+    /// Left wall points in survey order, where a left dimension was measured
+    pub left_wall: Vec<EastNorthElevation>,
+    /// Right wall points in survey order, where a right dimension was measured
+    pub right_wall: Vec<EastNorthElevation>,
+}
+
+impl PassageOutline {
+    /// The closed outline, up the left wall and back down the right
+    #[must_use]
+    pub fn polygon(&self) -> Vec<EastNorthElevation> {
+        self.left_wall
+            .iter()
+            .chain(self.right_wall.iter().rev())
+            .copied()
+            .collect()
+    }
+}
+
+/// Triangle mesh of the passage walls
+/// Each stretch of passage is a closed tube, with a ring of four vertices at every cross-section.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PassageMesh {
+    /// Vertex locations in meters
+    pub vertices: Vec<EastNorthElevation>,
+    /// Indices into the vertices, counterclockwise seen from outside the tube
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl PassageMesh {
+    fn add_tube(&mut self, sections: &[CrossSection]) {
+        if sections.len() < 2 {
+            return;
+        }
+        let start = self.vertices.len();
+        for section in sections {
+            self.vertices.extend(section.ring());
+        }
+        for ring in 0..sections.len() - 1 {
+            let here = start + ring * 4;
+            let next = here + 4;
+            for corner in 0..4 {
+                let following = (corner + 1) % 4;
+                self.triangles
+                    .push([here + corner, next + following, next + corner]);
+                self.triangles
+                    .push([here + corner, here + following, next + following]);
+            }
+        }
+        let end = start + (sections.len() - 1) * 4;
+        self.triangles.push([start, start + 2, start + 1]);
+        self.triangles.push([start, start + 3, start + 2]);
+        self.triangles.push([end, end + 1, end + 2]);
+        self.triangles.push([end, end + 2, end + 3]);
+    }
+}
+
+/// A passage dimension in meters, leaving out missing, continuing and negative readings
+fn dimension(measurement: Measurement) -> Option<f64> {
+    measurement
+        .value()
+        .filter(|value| *value >= 0.0)
+        .map(|value| value * FEET_TO_METERS)
+}
+
+fn is_drawn(shot: &Shot) -> bool {
+    !shot
+        .flags
+        .as_ref()
+        .is_some_and(|flags| flags.exclude_from_plotting || flags.exclude_from_processing)
+}
+
+/// Stretches of consecutive shots in a survey which each start where the last ended,
+/// leaving out shots flagged P or X
+fn stretches(survey: &Survey) -> Vec<Vec<&Shot>> {
+    let mut stretches: Vec<Vec<&Shot>> = Vec::new();
+    let mut continues = false;
+    for shot in &survey.shots {
+        if !is_drawn(shot) {
+            continues = false;
+            continue;
+        }
+        match stretches.last_mut() {
+            Some(stretch)
+                if continues && stretch.last().is_some_and(|last| last.to == shot.from) =>
+            {
+                stretch.push(shot);
+            }
+            _ => stretches.push(vec![shot]),
+        }
+        continues = true;
+    }
+    stretches
+}
+
+/// Cross-sections along a stretch of passage, one for each shot with any dimensions
+fn stretch_sections(
+    survey: &Survey,
+    stretch: &[&Shot],
+    coordinates: &HashMap<String, EastNorthElevation>,
+) -> Vec<CrossSection> {
+    let association = survey
+        .parameters
+        .format
+        .as_ref()
+        .map_or(LrudAssociation::From, SurveyFormat::lrud_station);
+    let mut sections = Vec::new();
+    let mut facing = None;
+    for shot in stretch {
+        let (Some(from), Some(to)) = (coordinates.get(&shot.from), coordinates.get(&shot.to))
+        else {
+            continue;
+        };
+        let vector = *to - *from;
+        if vector.easting.hypot(vector.northing) > MIN_HORIZONTAL_LENGTH {
+            facing = Some(vector.easting.atan2(vector.northing).to_degrees());
+        }
+        let (left, right, up, down) = (
+            dimension(shot.left),
+            dimension(shot.right),
+            dimension(shot.up),
+            dimension(shot.down),
+        );
+        if left.is_none() && right.is_none() && up.is_none() && down.is_none() {
+            continue;
+        }
+        let (station, location) = match association {
+            LrudAssociation::From => (&shot.from, *from),
+            LrudAssociation::To => (&shot.to, *to),
+        };
+        sections.push(CrossSection {
+            station: station.clone(),
+            location,
+            facing: facing.unwrap_or(0.0),
+            left,
+            right,
+            up,
+            down,
+        });
+    }
+    sections
+}
+
+fn outline(sections: &[CrossSection]) -> Option<PassageOutline> {
+    (sections.len() >= 2).then(|| PassageOutline {
+        stations: sections
+            .iter()
+            .map(|section| section.station.clone())
+            .collect(),
+        left_wall: sections
+            .iter()
+            .filter_map(CrossSection::left_wall)
+            .collect(),
+        right_wall: sections
+            .iter()
+            .filter_map(CrossSection::right_wall)
+            .collect(),
+    })
+}
+
+/// Cross-sections for each stretch of passage in the surveys
+fn all_sections<'a>(
+    surveys: impl IntoIterator<Item = &'a Survey>,
+    coordinates: &HashMap<String, EastNorthElevation>,
+) -> Vec<Vec<CrossSection>> {
+    surveys
+        .into_iter()
+        .flat_map(|survey| {
+            stretches(survey)
+                .into_iter()
+                .map(move |stretch| stretch_sections(survey, &stretch, coordinates))
+        })
+        .filter(|sections| !sections.is_empty())
+        .collect()
+}
+
+fn mesh(stretches: &[Vec<CrossSection>]) -> PassageMesh {
+    let mut mesh = PassageMesh::default();
+    for sections in stretches {
+        mesh.add_tube(sections);
+    }
+    mesh
+}
+
+impl Survey {
+    /// Build a cross-section for every shot with passage dimensions
+    /// Dimensions are placed at the station the survey's FORMAT associates them with,
+    /// facing along the shot. Shots flagged P or X are left out.
+    /// Positions come from [`Survey::station_coordinates`].
+    /// # Returns
+    /// The cross-sections in survey order
+    #[must_use]
+    pub fn cross_sections(&self) -> Vec<CrossSection> {
+        all_sections([self], &self.station_coordinates())
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Build plan view outlines of the passage
+    /// Each run of consecutive shots, each starting where the last ended, forms one outline.
+    #[must_use]
+    pub fn passage_outlines(&self) -> Vec<PassageOutline> {
+        all_sections([self], &self.station_coordinates())
+            .iter()
+            .filter_map(|sections| outline(sections))
+            .collect()
+    }
+
+    /// Build a closed tube mesh of the passage walls, one tube for each run of consecutive shots
+    #[must_use]
+    pub fn passage_mesh(&self) -> PassageMesh {
+        mesh(&all_sections([self], &self.station_coordinates()))
+    }
+}
+
+impl Project<Loaded> {
+    /// Build a cross-section for every shot in the project with passage dimensions
+    /// Positions come from [`Project::station_coordinates`], see [`Survey::cross_sections`].
+    #[must_use]
+    pub fn cross_sections(&self) -> Vec<CrossSection> {
+        all_sections(
+            self.surveys().map(|(_, survey)| survey),
+            &self.station_coordinates(),
+        )
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Build plan view outlines of every run of passage in the project, see [`Survey::passage_outlines`]
+    #[must_use]
+    pub fn passage_outlines(&self) -> Vec<PassageOutline> {
+        all_sections(
+            self.surveys().map(|(_, survey)| survey),
+            &self.station_coordinates(),
+        )
+        .iter()
+        .filter_map(|sections| outline(sections))
+        .collect()
+    }
+
+    /// Build a closed tube mesh of the passage walls for the whole project
+    #[must_use]
+    pub fn passage_mesh(&self) -> PassageMesh {
+        mesh(&all_sections(
+            self.surveys().map(|(_, survey)| survey),
+            &self.station_coordinates(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{
+        test_utils::survey, BearingUnits, InclinationUnits, LengthUnits, LrudItem, ShotFlags,
+        ShotItem,
+    };
+
+    fn shot(from: &str, to: &str, azimuth: f64, lrud: [f64; 4]) -> Shot {
+        let mut shot = Shot::new(from, to, 10.0, azimuth, 0.0);
+        [shot.left, shot.right, shot.up, shot.down] = lrud.map(Measurement::Measured);
+        shot
+    }
+
+    fn assert_location(location: EastNorthElevation, easting: f64, northing: f64, up: f64) {
+        assert_float_eq!(location.easting, easting, abs <= 1e-9);
+        assert_float_eq!(location.northing, northing, abs <= 1e-9);
+        assert_float_eq!(location.up, up, abs <= 1e-9);
+    }
+
+    #[test]
+    fn walls_face_along_the_shot() {
+        let mut survey = survey(
+            "P",
+            vec![
+                shot("P1", "P2", 90.0, [2.0, 4.0, 1.0, 3.0]),
+                shot("P2", "P3", 0.0, [5.0, -9.9, -2.0, 0.0]),
+            ],
+        );
+        survey.shots[1].down = Measurement::MISSING_DIMENSION;
+        let sections = survey.cross_sections();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].station, "P1");
+        assert_float_eq!(sections[0].facing, 90.0, abs <= 1e-9);
+        // Heading east, the left wall is to the north
+        assert_location(
+            sections[0].left_wall().unwrap(),
+            0.0,
+            2.0 * FEET_TO_METERS,
+            0.0,
+        );
+        assert_location(
+            sections[0].right_wall().unwrap(),
+            0.0,
+            -4.0 * FEET_TO_METERS,
+            0.0,
+        );
+        assert_location(sections[0].ceiling().unwrap(), 0.0, 0.0, FEET_TO_METERS);
+        assert_location(
+            sections[0].floor().unwrap(),
+            0.0,
+            0.0,
+            -3.0 * FEET_TO_METERS,
+        );
+
+        // Missing and negative dimensions have no walls
+        assert_eq!(sections[1].right, None);
+        assert_eq!(sections[1].up, None);
+        assert_eq!(sections[1].down, None);
+        assert_eq!(sections[1].ceiling(), None);
+        assert_location(
+            sections[1].left_wall().unwrap(),
+            5.0 * FEET_TO_METERS,
+            0.0,
+            0.0,
+        );
+    }
+
+    #[test]
+    fn lrud_association() {
+        let mut survey = survey(
+            "P",
+            vec![
+                shot("P1", "P2", 90.0, [2.0, 2.0, 2.0, 2.0]),
+                shot("P2", "P3", 90.0, [1.0, 1.0, 1.0, 1.0]),
+            ],
+        );
+        survey.parameters.format = Some(SurveyFormat {
+            bearing_units: BearingUnits::Degrees,
+            length_units: LengthUnits::DecimalFeet,
+            passage_units: LengthUnits::DecimalFeet,
+            inclination_units: InclinationUnits::Degrees,
+            passage_dimension_order: [
+                LrudItem::Left,
+                LrudItem::Up,
+                LrudItem::Down,
+                LrudItem::Right,
+            ],
+            shot_item_order: vec![ShotItem::Length, ShotItem::Azimuth, ShotItem::Inclination],
+            backsight_mode: None,
+            lrud_association: Some(LrudAssociation::To),
+        });
+        let sections = survey.cross_sections();
+        assert_eq!(sections[0].station, "P2");
+        assert_location(sections[0].location, 10.0 * FEET_TO_METERS, 0.0, 0.0);
+        assert_eq!(sections[1].station, "P3");
+    }
+
+    #[test]
+    fn outlines_and_flags() {
+        let mut splay = shot("P2", "P2a", 180.0, [1.0, 1.0, 1.0, 1.0]);
+        splay.flags = Some(ShotFlags {
+            exclude_from_plotting: true,
+            ..ShotFlags::default()
+        });
+        let survey = survey(
+            "P",
+            vec![
+                shot("P1", "P2", 90.0, [1.0, 1.0, 1.0, 1.0]),
+                shot("P2", "P3", 90.0, [1.0, -9.9, 1.0, 1.0]),
+                shot("P3", "P4", 90.0, [1.0, 1.0, 1.0, 1.0]),
+                splay,
+                shot("P4", "P5", 90.0, [1.0, 1.0, 1.0, 1.0]),
+                shot("P5", "P6", 90.0, [1.0, 1.0, 1.0, 1.0]),
+            ],
+        );
+        assert!(survey
+            .cross_sections()
+            .iter()
+            .all(|section| section.station != "P2a" && (section.facing - 90.0).abs() < 1e-9));
+
+        // The flagged splay breaks the run of shots in two
+        let outlines = survey.passage_outlines();
+        assert_eq!(outlines.len(), 2);
+        assert_eq!(outlines[0].stations, vec!["P1", "P2", "P3"]);
+        assert_eq!(outlines[0].left_wall.len(), 3);
+        assert_eq!(outlines[0].right_wall.len(), 2);
+        let polygon = outlines[0].polygon();
+        assert_eq!(polygon.len(), 5);
+        assert_location(polygon[4], 0.0, -FEET_TO_METERS, 0.0);
+        assert_eq!(outlines[1].stations, vec!["P4", "P5"]);
+
+        let mesh = survey.passage_mesh();
+        assert_eq!(mesh.vertices.len(), (3 + 2) * 4);
+        // Eight triangles between each pair of rings, and two on each end
+        assert_eq!(mesh.triangles.len(), (2 * 8 + 4) + (8 + 4));
+        assert!(mesh
+            .triangles
+            .iter()
+            .flatten()
+            .all(|&index| index < mesh.vertices.len()));
+    }
+
+    #[test]
+    fn mesh_faces_outward() {
+        let survey = survey(
+            "P",
+            vec![
+                shot("P1", "P2", 90.0, [1.0, 2.0, 3.0, 4.0]),
+                shot("P2", "P3", 90.0, [2.0, 1.0, 4.0, 3.0]),
+            ],
+        );
+        let mesh = survey.passage_mesh();
+        assert_eq!(mesh.triangles.len(), 8 + 4);
+        // Every face points away from the centerline, which runs east along the x axis
+        for triangle in &mesh.triangles {
+            let [first, second, third] = triangle.map(|index| mesh.vertices[index]);
+            let (along, across) = (second - first, third - first);
+            let normal = EastNorthElevation::from_meters(
+                along.northing * across.up - along.up * across.northing,
+                along.up * across.easting - along.easting * across.up,
+                along.easting * across.northing - along.northing * across.easting,
+            );
+            let centroid = EastNorthElevation::from_meters(
+                (first.easting + second.easting + third.easting) / 3.0,
+                (first.northing + second.northing + third.northing) / 3.0,
+                (first.up + second.up + third.up) / 3.0,
+            );
+            let outward = if normal.northing.abs() + normal.up.abs() > 1e-12 {
+                normal.northing * centroid.northing + normal.up * centroid.up
+            } else {
+                // End caps face along the passage, away from the middle of their tube
+                normal.easting * (centroid.easting - 5.0 * FEET_TO_METERS)
+            };
+            assert!(outward > 0.0);
+        }
+    }
+
+    #[test]
+    fn project_passages() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        let coordinates = project.station_coordinates();
+        let sections = project.cross_sections();
+        assert!(!sections.is_empty());
+        for section in &sections {
+            assert_eq!(section.location, coordinates[&section.station]);
+        }
+        assert!(!project.passage_outlines().is_empty());
+        let mesh = project.passage_mesh();
+        assert!(!mesh.triangles.is_empty());
+    }
+}
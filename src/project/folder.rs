@@ -0,0 +1,67 @@
+use super::{Project, SurveyFile};
+
+/// A folder in a project's folder tree, as organized in the Compass project manager
+///
+/// Folders are derived from the folder paths of the survey files they contain,
+/// so a folder without any files in it does not appear in the tree.
+#[derive(Debug)]
+pub struct ProjectFolder<'a, S> {
+    /// The folder's name, empty for the project's root folder
+    pub name: &'a str,
+    /// Folders nested directly inside this one, in the order they appear in the project
+    pub folders: Vec<ProjectFolder<'a, S>>,
+    /// Survey files directly inside this folder, in the order they appear in the project
+    pub survey_files: Vec<&'a SurveyFile<S>>,
+}
+
+impl<'a, S> ProjectFolder<'a, S> {
+    fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            folders: Vec::new(),
+            survey_files: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_project(project: &'a Project<S>) -> Self {
+        let mut root = ProjectFolder::new("");
+        for survey_file in &project.survey_files {
+            let mut folder = &mut root;
+            for name in &survey_file.folder {
+                let index = if let Some(index) =
+                    folder.folders.iter().position(|child| child.name == name)
+                {
+                    index
+                } else {
+                    folder.folders.push(ProjectFolder::new(name));
+                    folder.folders.len() - 1
+                };
+                folder = &mut folder.folders[index];
+            }
+            folder.survey_files.push(survey_file);
+        }
+        root
+    }
+
+    /// Find a nested folder by its path of folder names relative to this folder
+    #[must_use]
+    pub fn folder(&self, path: &[&str]) -> Option<&ProjectFolder<'a, S>> {
+        let Some((name, rest)) = path.split_first() else {
+            return Some(self);
+        };
+        self.folders
+            .iter()
+            .find(|folder| folder.name == *name)?
+            .folder(rest)
+    }
+
+    /// All survey files in this folder and its subfolders, depth first
+    #[must_use]
+    pub fn all_survey_files(&self) -> Vec<&'a SurveyFile<S>> {
+        let mut survey_files = self.survey_files.clone();
+        for folder in &self.folders {
+            survey_files.extend(folder.all_survey_files());
+        }
+        survey_files
+    }
+}
@@ -5,14 +5,24 @@
 //! The compass file format is documented here:
 //! [Compass Project Documentation](https://www.fountainware.com/compass/HTML_Help/Project_Manager/projectfileformat.htm)
 //!
+mod folder;
+mod parameters;
 mod parser;
+mod save;
+mod serializer;
+mod station;
+
+pub use folder::ProjectFolder;
+pub use parameters::{DeclinationMode, ProjectParameters};
+pub use station::{FixUnits, Station, StationFix};
 
 use std::{
+    collections::HashMap,
     marker::PhantomData,
     path::{Path, PathBuf},
 };
 
-use crate::{EastNorthElevation, Error, Survey, UtmLocation};
+use crate::{geodesy, EastNorthElevation, Error, Shot, Survey, UtmLocation};
 
 /// Compass projects can be defined in a variety of geodetic datums.
 /// The datum is used to convert between the geodetic coordinates used in the survey data.
@@ -44,12 +54,6 @@ pub enum Datum {
     Wgs1984,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Station {
-    name: String,
-    location: Option<EastNorthElevation>,
-}
-
 /// Marker type for survey and project files which have not been fully loaded yet
 #[derive(Clone, Debug, PartialEq)]
 pub struct Unloaded;
@@ -61,10 +65,73 @@ pub struct Loaded;
 pub struct SurveyFile<S> {
     pub file_path: PathBuf,
     pub project_stations: Vec<Station>,
+    /// Names of the folders containing this file in the project, outermost first
+    /// Empty for files at the top level of the project
+    pub folder: Vec<String>,
+    /// Comments written inside the file's entry in the project file
+    comments: Vec<String>,
+    /// Parameters from a `!` line between file entries, overriding the project parameters for this file
+    pub parameters: Option<ProjectParameters>,
     surveys: Vec<Survey>,
     state: PhantomData<S>,
 }
 
+impl<S> SurveyFile<S> {
+    /// Find a station listed in this file's entry in the project file
+    #[must_use]
+    pub fn station(&self, name: &str) -> Option<&Station> {
+        self.project_stations
+            .iter()
+            .find(|station| station.name() == name)
+    }
+
+    /// Stations tying this file to the project's other survey files
+    pub fn link_stations(&self) -> impl Iterator<Item = &Station> {
+        self.project_stations
+            .iter()
+            .filter(|station| station.is_link())
+    }
+
+    /// Stations holding this file's surveys at known coordinates
+    pub fn fixed_stations(&self) -> impl Iterator<Item = &Station> {
+        self.project_stations
+            .iter()
+            .filter(|station| station.is_fixed())
+    }
+
+    /// List a link station in this file's entry, if it isn't listed already
+    pub fn add_link_station(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        if self.station(&name).is_none() {
+            self.project_stations.push(Station::link(name));
+        }
+    }
+
+    /// Fix a station at known coordinates, listing it in this file's entry if it isn't listed already
+    pub fn fix_station(&mut self, name: impl Into<String>, fix: StationFix) {
+        let name = name.into();
+        match self
+            .project_stations
+            .iter_mut()
+            .find(|station| station.name() == name)
+        {
+            Some(station) => station.set_fix(Some(fix)),
+            None => self.project_stations.push(Station::fixed(name, fix)),
+        }
+    }
+
+    /// Remove a station from this file's entry
+    /// # Returns
+    /// The removed station, `None` if the station wasn't listed
+    pub fn remove_station(&mut self, name: &str) -> Option<Station> {
+        let index = self
+            .project_stations
+            .iter()
+            .position(|station| station.name() == name)?;
+        Some(self.project_stations.remove(index))
+    }
+}
+
 impl SurveyFile<Unloaded> {
     /// Load the survey data file from disk
     /// Consumes the `SurveyFile<Unloaded>` and returns a `SurveyFile<Loaded>` with the survey data populated
@@ -83,6 +150,9 @@ impl SurveyFile<Unloaded> {
         Ok(SurveyFile {
             file_path: self.file_path,
             project_stations: self.project_stations,
+            folder: self.folder,
+            comments: self.comments,
+            parameters: self.parameters,
             surveys,
             state: PhantomData,
         })
@@ -93,29 +163,130 @@ pub struct Project<S> {
     pub file_path: PathBuf,
     pub base_location: UtmLocation,
     pub datum: Datum,
-    /// The UTM zone used for fixed stations in the project
+    /// The UTM zone used for fixed stations in the project, from the `$` line
+    /// When absent, fixed stations are in the base location's zone
     pub utm_zone: Option<u8>,
+    /// Processing options from the `!` line preceding the survey files
+    pub parameters: Option<ProjectParameters>,
+    /// Whether UTM convergence is applied, `%` enables it and `*` disables it
+    pub utm_convergence: Option<bool>,
     pub survey_files: Vec<SurveyFile<S>>,
+    /// Comments written outside the survey file entries,
+    /// with the number of survey files listed before each so they're written back in place
+    comments: Vec<(usize, String)>,
     state: PhantomData<S>,
 }
 
+impl<S> Project<S> {
+    /// Comments written outside the survey file entries, in the order they appear
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.comments.iter().map(|(_, comment)| comment.as_str())
+    }
+
+    /// Add a comment after the survey files listed so far
+    pub fn add_comment(&mut self, comment: impl Into<String>) {
+        self.comments
+            .push((self.survey_files.len(), comment.into()));
+    }
+
+    /// The project's survey files arranged in their folder tree
+    #[must_use]
+    pub fn folder_tree(&self) -> ProjectFolder<'_, S> {
+        ProjectFolder::from_project(self)
+    }
+
+    /// Survey files whose entries in the project file list the station, in project order
+    #[must_use]
+    pub fn files_referencing_station(&self, name: &str) -> Vec<&SurveyFile<S>> {
+        self.survey_files
+            .iter()
+            .filter(|survey_file| survey_file.station(name).is_some())
+            .collect()
+    }
+
+    /// The UTM zone the coordinates of the project's fixed stations are written in
+    #[must_use]
+    pub fn fixed_station_zone(&self) -> u8 {
+        self.utm_zone.unwrap_or(self.base_location.zone)
+    }
+
+    /// Check the `$` zone of the fixed stations against the base location's zone
+    /// A cave's fixed stations can straddle a zone boundary, so the zones must be the same or neighbours.
+    /// # Errors
+    /// - [`Error::InvalidUtmZone`] If the fixed stations are more than one zone away from the base location
+    pub fn validate_utm_zone(&self) -> Result<(), Error> {
+        let zone = self.fixed_station_zone();
+        let base_zone = self.base_location.zone;
+        // Zone 60 neighbours zone 1 across the antimeridian
+        if matches!(
+            (i16::from(zone) - i16::from(base_zone)).rem_euclid(60),
+            0 | 1 | 59
+        ) {
+            Ok(())
+        } else {
+            Err(Error::InvalidUtmZone(format!(
+                "fixed stations are in zone {zone}, which doesn't neighbour the base location's zone {base_zone}"
+            )))
+        }
+    }
+
+    /// Locations of every fixed station in the project, keyed by station name
+    /// Fixes written in a different zone than the base location are reprojected onto the base location's zone,
+    /// so all of the returned coordinates share a grid
+    #[must_use]
+    pub fn fixed_station_locations(&self) -> HashMap<&str, EastNorthElevation> {
+        let from_zone = self.fixed_station_zone();
+        self.survey_files
+            .iter()
+            .flat_map(|survey_file| &survey_file.project_stations)
+            .filter_map(|station| {
+                let fix = station.fix()?;
+                let location = geodesy::reproject_to_zone(
+                    self.datum.ellipsoid(),
+                    self.base_location.hemisphere,
+                    fix.location(),
+                    from_zone,
+                    self.base_location.zone,
+                );
+                Some((station.name(), location))
+            })
+            .collect()
+    }
+
+    /// Serialize the project to the makefile format Compass reads
+    /// Fixed station coordinates are written in the units they were specified in
+    #[must_use]
+    pub fn serialize(&self) -> String {
+        serializer::serialize_project(self)
+    }
+}
+
 impl Project<Unloaded> {
     /// Read a Compass project file from disk
     /// The project file is read from disk and parsed into a `ProjectFile` struct,
-    /// but this does not parse the referenced survey data files
+    /// but this does not parse the referenced survey data files.
+    /// The base location and fixed station zone are read as written, without being checked.
+    /// Call [`Project::validate_base_location`] and [`Project::validate_utm_zone`] before relying on them.
     /// # Returns
     /// `ProjectFile` representing the contents of the project file
     /// # Errors
     /// - [`Error::ProjectFileNotFound`] If the file does not exist
     /// - [`Error::CouldntReadFile`] If the file cannot be read
+    /// - [`Error::CouldntParseProject`] If the file contains an element which isn't understood
     pub fn read(file_path: impl AsRef<Path>) -> Result<Self, Error> {
         let path = file_path.as_ref().to_path_buf();
         if !path.exists() {
             return Err(Error::ProjectFileNotFound(path));
         }
         let file_contents = std::fs::read_to_string(&path).map_err(Error::CouldntReadFile)?;
-        let (_, project) = parser::parse_compass_project(path, &file_contents)
-            .map_err(|e| Error::CouldntParseProject(e.to_string()))?;
+        let (_, project) =
+            parser::parse_compass_project(path, &file_contents).map_err(|e| match e {
+                nom::Err::Failure(e) => Error::CouldntParseProject(format!(
+                    "Unrecognized project element: {}",
+                    e.input.lines().next().unwrap_or_default()
+                )),
+                e => Error::CouldntParseProject(e.to_string()),
+            })?;
         Ok(project)
     }
 
@@ -142,14 +313,203 @@ impl Project<Unloaded> {
             base_location: self.base_location,
             datum: self.datum,
             utm_zone: self.utm_zone,
+            parameters: self.parameters,
+            utm_convergence: self.utm_convergence,
             survey_files,
+            comments: self.comments,
             state: PhantomData::<Loaded>,
         })
     }
 }
 
+/// A shot in a loaded project, along with the survey and file it belongs to
+#[derive(Clone, Copy, Debug)]
+pub struct ProjectShot<'a> {
+    pub survey_file: &'a SurveyFile<Loaded>,
+    pub survey: &'a Survey,
+    pub shot: &'a Shot,
+}
+
+impl SurveyFile<Loaded> {
+    /// Programmatically create a survey file without any surveys
+    /// The file is placed at the top level of the project, set [`SurveyFile::folder`] to move it into a folder
+    #[must_use]
+    pub fn new(file_path: impl AsRef<Path>) -> Self {
+        Self {
+            file_path: file_path.as_ref().to_path_buf(),
+            project_stations: Vec::new(),
+            folder: Vec::new(),
+            comments: Vec::new(),
+            parameters: None,
+            surveys: Vec::new(),
+            state: PhantomData::<Loaded>,
+        }
+    }
+
+    /// Add a survey to the end of the file
+    /// # Returns
+    /// The added survey, so shots can be added to it
+    pub fn add_survey(&mut self, survey: Survey) -> &mut Survey {
+        let index = self.surveys.len();
+        self.surveys.push(survey);
+        &mut self.surveys[index]
+    }
+
+    /// Find a survey in the file by its name, to change its data
+    pub fn survey_mut(&mut self, name: &str) -> Option<&mut Survey> {
+        self.surveys.iter_mut().find(|survey| survey.name == name)
+    }
+
+    /// The surveys in the file, in file order, for editing in place
+    pub fn surveys_mut(&mut self) -> &mut [Survey] {
+        &mut self.surveys
+    }
+
+    /// Remove a survey from the file
+    /// # Returns
+    /// The removed survey, `None` if the file has no survey with that name
+    pub fn remove_survey(&mut self, name: &str) -> Option<Survey> {
+        let index = self.surveys.iter().position(|survey| survey.name == name)?;
+        Some(self.surveys.remove(index))
+    }
+
+    /// The surveys in the file, in the order they appear in it
+    #[must_use]
+    pub fn surveys(&self) -> &[Survey] {
+        &self.surveys
+    }
+
+    /// Find a survey in the file by its name
+    #[must_use]
+    pub fn survey(&self, name: &str) -> Option<&Survey> {
+        self.surveys.iter().find(|survey| survey.name == name)
+    }
+
+    /// Every shot in the file, along with the survey it belongs to
+    pub fn shots(&self) -> impl Iterator<Item = (&Survey, &Shot)> {
+        self.surveys
+            .iter()
+            .flat_map(|survey| survey.shots.iter().map(move |shot| (survey, shot)))
+    }
+
+    /// Every shot in the file which starts or ends at a station
+    /// # Errors
+    /// - [`Error::StationNotFound`] If no shot in the file touches the station
+    pub fn shots_at_station(&self, station: &str) -> Result<Vec<(&Survey, &Shot)>, Error> {
+        let shots: Vec<_> = self
+            .shots()
+            .filter(|(_, shot)| shot.from == station || shot.to == station)
+            .collect();
+        if shots.is_empty() {
+            return Err(Error::StationNotFound(station.to_string()));
+        }
+        Ok(shots)
+    }
+}
+
 impl Project<Loaded> {
+    /// Every survey in the project, along with the file it belongs to, in project order
+    pub fn surveys(&self) -> impl Iterator<Item = (&SurveyFile<Loaded>, &Survey)> {
+        self.survey_files.iter().flat_map(|survey_file| {
+            survey_file
+                .surveys
+                .iter()
+                .map(move |survey| (survey_file, survey))
+        })
+    }
+
+    /// Find a survey by its name, along with the file it belongs to
+    /// Survey names are expected to be unique within a project, the first match is returned
+    #[must_use]
+    pub fn survey(&self, name: &str) -> Option<(&SurveyFile<Loaded>, &Survey)> {
+        self.surveys().find(|(_, survey)| survey.name == name)
+    }
+
+    /// Every shot in the project, along with the file and survey it belongs to
+    pub fn shots(&self) -> impl Iterator<Item = ProjectShot<'_>> {
+        self.surveys().flat_map(|(survey_file, survey)| {
+            survey.shots.iter().map(move |shot| ProjectShot {
+                survey_file,
+                survey,
+                shot,
+            })
+        })
+    }
+
+    /// Every shot in the project which starts or ends at a station
+    /// # Errors
+    /// - [`Error::StationNotFound`] If no shot in the project touches the station
+    pub fn shots_at_station(&self, station: &str) -> Result<Vec<ProjectShot<'_>>, Error> {
+        let shots: Vec<_> = self
+            .shots()
+            .filter(|shot| shot.shot.from == station || shot.shot.to == station)
+            .collect();
+        if shots.is_empty() {
+            return Err(Error::StationNotFound(station.to_string()));
+        }
+        Ok(shots)
+    }
+
+    /// Add a survey file to the end of the project
+    /// Folders in the file's [`SurveyFile::folder`] path are created as needed
+    /// # Returns
+    /// The added survey file, so stations and surveys can be added to it
+    pub fn add_survey_file(&mut self, survey_file: SurveyFile<Loaded>) -> &mut SurveyFile<Loaded> {
+        let index = self.survey_files.len();
+        self.survey_files.push(survey_file);
+        &mut self.survey_files[index]
+    }
+
+    /// Find a survey file by the path it is listed under in the project, to change its data
+    pub fn survey_file_mut(
+        &mut self,
+        file_path: impl AsRef<Path>,
+    ) -> Option<&mut SurveyFile<Loaded>> {
+        let file_path = file_path.as_ref();
+        self.survey_files
+            .iter_mut()
+            .find(|survey_file| survey_file.file_path == file_path)
+    }
+
+    /// Remove a survey file from the project
+    /// # Returns
+    /// The removed file, `None` if the project doesn't list a file at that path
+    pub fn remove_survey_file(
+        &mut self,
+        file_path: impl AsRef<Path>,
+    ) -> Option<SurveyFile<Loaded>> {
+        let file_path = file_path.as_ref();
+        let index = self
+            .survey_files
+            .iter()
+            .position(|survey_file| survey_file.file_path == file_path)?;
+        Some(self.survey_files.remove(index))
+    }
+
+    /// Save the project file and all of its survey data files into a directory
+    /// The project file keeps its file name, survey files are written to their paths relative to the directory,
+    /// and any missing directories are created.
+    /// Each file is written to a temporary file first, and only moved into place once every file has been written.
+    /// If moving a file into place fails, the files already replaced are restored.
+    /// Once every file is in place, failing to keep or remove a backup doesn't undo the save,
+    /// the remaining backups are still handled and the first failure is returned.
+    /// # Arguments
+    /// - `directory` - The directory to save the project into
+    /// - `keep_backups` - Copy each file being replaced to the same name with `.bak` appended before replacing it
+    /// # Errors
+    /// - [`Error::CouldntWriteFile`] If a file or directory cannot be written, a survey file path is absolute or
+    ///   leaves the directory, or two files would be saved to the same path
+    pub fn save(&self, directory: impl AsRef<Path>, keep_backups: bool) -> Result<(), Error> {
+        save::save_project(self, directory.as_ref(), keep_backups)
+    }
+
     /// Programmatically create a new compass project
+    /// The base location's convergence angle is replaced with the one computed from its position.
+    /// # Arguments
+    /// - `file_path` - Path of the project file
+    /// - `base_location` - UTM location of the base location
+    /// - `datum` - Datum of the project's coordinates
+    /// - `utm_zone` - Zone the fixed stations are written in, when it differs from the base location's
     #[must_use]
     pub fn new(
         file_path: impl AsRef<Path>,
@@ -160,10 +520,16 @@ impl Project<Loaded> {
         let file_path = file_path.as_ref().to_path_buf();
         Self {
             file_path,
-            base_location,
+            base_location: UtmLocation {
+                convergence_angle: base_location.grid_convergence(datum),
+                ..base_location
+            },
             datum,
             utm_zone,
+            parameters: None,
+            utm_convergence: None,
             survey_files: Vec::new(),
+            comments: Vec::new(),
             state: PhantomData::<Loaded>,
         }
     }
@@ -171,6 +537,8 @@ impl Project<Loaded> {
 
 #[cfg(test)]
 mod tests {
+    use float_eq::assert_float_eq;
+
     use crate::{common_types::EastNorthElevation, Error};
 
     use super::*;
@@ -183,13 +551,220 @@ mod tests {
             UtmLocation {
                 east_north_elevation,
                 zone: 17,
+                hemisphere: crate::Hemisphere::North,
                 convergence_angle: 1.257_286,
             },
             Datum::Wgs1984,
             None,
         );
         assert!(new_project.survey_files.is_empty());
+        // The convergence passed in is replaced, west of the zone's central meridian grid north is west of true north
+        assert_float_eq!(
+            new_project.base_location.convergence_angle,
+            -0.844,
+            abs <= 0.001
+        );
+        assert!(new_project.validate_base_location().is_ok());
+    }
+
+    #[test]
+    fn build_project_in_code() {
+        let base_location = UtmLocation {
+            east_north_elevation: EastNorthElevation::from_meters(336_083.0, 3_301_724.0, 6.0),
+            zone: 17,
+            hemisphere: crate::Hemisphere::North,
+            convergence_angle: 1.257,
+        };
+        let mut project = Project::new("Ginnie.mak", base_location, Datum::Wgs1984, None);
+
+        let mut survey_file = SurveyFile::new("ballroom.dat");
+        survey_file.folder = vec!["Ginnie".to_string(), "Ballroom".to_string()];
+        let survey_file = project.add_survey_file(survey_file);
+        survey_file.fix_station(
+            "GB1",
+            StationFix::new(base_location.east_north_elevation, FixUnits::Meters),
+        );
+        survey_file.add_link_station("GB3");
+        survey_file.add_link_station("GB3");
+        let parameters = crate::Parameters {
+            declination: -4.5,
+            format: None,
+            correction_factors: None,
+            backsight_correction_factors: None,
+        };
+        let date = crate::Date {
+            month: 3,
+            day: 14,
+            year: 2024,
+        };
+        let survey = survey_file.add_survey(Survey::new(
+            "Ginnie Springs",
+            "GB",
+            date,
+            "Diver One;Diver Two",
+            parameters,
+        ));
+        survey
+            .shots
+            .push(Shot::new("GB1", "GB2", 40.0, 92.0, -10.0));
+        survey.shots.push(Shot::new("GB2", "GB3", 25.5, 181.0, 2.5));
+
+        let plain = project.add_survey_file(SurveyFile::new("cavern.dat"));
+        plain.add_link_station("GB3");
+
+        let tree = project.folder_tree();
+        assert_eq!(
+            tree.folder(&["Ginnie", "Ballroom"])
+                .unwrap()
+                .survey_files
+                .len(),
+            1
+        );
+        assert_eq!(project.files_referencing_station("GB3").len(), 2);
+        assert_eq!(project.shots_at_station("GB2").unwrap().len(), 2);
+
+        let (_, reparsed) =
+            parser::parse_compass_project(PathBuf::from("Ginnie.mak"), &project.serialize())
+                .unwrap();
+        assert_eq!(reparsed.survey_files[0].project_stations.len(), 2);
+        assert!(reparsed.survey_files[0].station("GB1").unwrap().is_fixed());
+        assert_eq!(reparsed.survey_files[0].folder, vec!["Ginnie", "Ballroom"]);
+        let surveys = Survey::parse_dat_file(&Survey::serialize_dat_file(
+            project.survey_files[0].surveys(),
+        ))
+        .unwrap();
+        assert_eq!(surveys, project.survey_files[0].surveys());
+
+        let survey_file = project.survey_file_mut("ballroom.dat").unwrap();
+        survey_file.survey_mut("GB").unwrap().shots.pop();
+        assert!(survey_file.remove_station("GB3").unwrap().is_link());
+        assert!(survey_file.remove_survey("GB").is_some());
+        assert!(project.remove_survey_file("cavern.dat").is_some());
+        assert!(project.remove_survey_file("cavern.dat").is_none());
+        assert_eq!(project.survey_files.len(), 1);
+    }
+
+    #[test]
+    fn save_to_directory() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let mut project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        project.survey_files[1].file_path = PathBuf::from("surface/Fulsurf.dat");
+
+        let directory =
+            std::env::temp_dir().join(format!("compass_data_save_{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&directory);
+        project.save(&directory, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(directory.join("Fulford.dat")).unwrap(),
+            include_str!("../../test_data/Fulford.dat")
+        );
+        assert!(directory.join("surface/Fulsurf.dat").exists());
+        assert!(!directory.join("Fulfords.mak.bak").exists());
+
+        let first_save = std::fs::read_to_string(directory.join("Fulfords.mak")).unwrap();
+        project.survey_files[0].remove_survey("A+");
+        project.save(&directory, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(directory.join("Fulfords.mak.bak")).unwrap(),
+            first_save
+        );
+        assert_eq!(
+            std::fs::read_to_string(directory.join("Fulford.dat.bak")).unwrap(),
+            include_str!("../../test_data/Fulford.dat")
+        );
+        let saved = Project::read(directory.join("Fulfords.mak"))
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        assert_eq!(saved.survey_files[0].surveys().len(), 24);
+        assert!(std::fs::read_dir(&directory).unwrap().all(|entry| !entry
+            .unwrap()
+            .path()
+            .to_string_lossy()
+            .ends_with(".tmp")));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn failed_save_restores_project() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let mut project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+
+        let directory =
+            std::env::temp_dir().join(format!("compass_data_rollback_{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&directory);
+        project.save(&directory, false).unwrap();
+        let first_project = std::fs::read_to_string(directory.join("Fulfords.mak")).unwrap();
+
+        // A directory in place of the last survey file stops it being moved into place,
+        // after the project file and the first survey file have been replaced
+        std::fs::remove_file(directory.join("Fulsurf.dat")).unwrap();
+        std::fs::create_dir_all(directory.join("Fulsurf.dat/inside")).unwrap();
+        project.survey_files[0].remove_survey("A+");
+        assert!(matches!(
+            project.save(&directory, true),
+            Err(Error::CouldntWriteFile(_))
+        ));
+        assert_eq!(
+            std::fs::read_to_string(directory.join("Fulfords.mak")).unwrap(),
+            first_project
+        );
+        assert_eq!(
+            std::fs::read_to_string(directory.join("Fulford.dat")).unwrap(),
+            include_str!("../../test_data/Fulford.dat")
+        );
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert!(
+            files
+                .iter()
+                .all(|file| !file
+                    .extension()
+                    .is_some_and(|extension| ["tmp", "orig", "bak"]
+                        .iter()
+                        .any(|leftover| extension == *leftover))),
+            "{files:?}"
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn save_rejects_paths_outside_directory() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let mut project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        let directory =
+            std::env::temp_dir().join(format!("compass_data_reject_{}", std::process::id()));
+
+        for path in [
+            PathBuf::from("../Fulsurf.dat"),
+            PathBuf::from("surface/../../Fulsurf.dat"),
+            std::env::temp_dir().join("Fulsurf.dat"),
+            PathBuf::from("Fulford.dat"),
+            PathBuf::from("Fulfords.mak"),
+        ] {
+            project.survey_files[1].file_path = path;
+            assert!(matches!(
+                project.save(&directory, false),
+                Err(Error::CouldntWriteFile(_))
+            ));
+        }
+        assert!(!directory.exists());
     }
+
     #[test]
     fn bad_path() {
         let path = PathBuf::from("does_not_exist.mak");
@@ -206,4 +781,213 @@ mod tests {
         assert_eq!(read_project.survey_files.len(), 2);
         let _loaded_project = read_project.load_survey_files().unwrap();
     }
+
+    #[test]
+    fn query_loaded_surveys() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+
+        assert_eq!(project.surveys().count(), 29);
+        let fulford = &project.survey_files[0];
+        assert_eq!(fulford.surveys().len(), 25);
+        assert_eq!(fulford.survey("A+").unwrap().name, "A+");
+        assert!(fulford.survey("SS").is_none());
+
+        let (survey_file, survey) = project.survey("SS").unwrap();
+        assert_eq!(survey_file.file_path, PathBuf::from("Fulsurf.dat"));
+        assert_eq!(survey.name, "SS");
+        assert!(project.survey("NOPE").is_none());
+
+        let shot_count: usize = project
+            .surveys()
+            .map(|(_, survey)| survey.shots.len())
+            .sum();
+        assert_eq!(project.shots().count(), shot_count);
+        assert_eq!(
+            fulford.shots().count(),
+            fulford.surveys().iter().map(|s| s.shots.len()).sum()
+        );
+
+        let a1 = project.shots_at_station("A1").unwrap();
+        assert_eq!(a1.len(), 5);
+        assert!(a1
+            .iter()
+            .all(|found| found.shot.from == "A1" || found.shot.to == "A1"));
+        assert_eq!(fulford.shots_at_station("A1").unwrap().len(), 3);
+        assert!(matches!(
+            project.shots_at_station("NOWHERE"),
+            Err(Error::StationNotFound(name)) if name == "NOWHERE"
+        ));
+    }
+
+    #[test]
+    fn serialize_compass_sample() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let mut project = Project::read(&sample_path).unwrap();
+        assert_eq!(project.comments().collect::<Vec<_>>(), vec!["", ""]);
+        assert_eq!(
+            project.serialize(),
+            "@357715.717,4372837.574,3048.000,13,-1.050;\r\n\
+             &North American 1983;\r\n\
+             $13;\r\n\
+             /\r\n\
+             #Fulford.dat,\r\n  \
+             A1[F,1173607.995,14346579.967,10000.000],\r\n  \
+             SC3[F,1173537.730,14346710.958,9938.648],\r\n  \
+             S4[F,1173638.451,14346578.084,10020.013],\r\n  \
+             SS6[F,1173818.570,14346406.496,10018.701];\r\n\
+             /\r\n\
+             #Fulsurf.dat;\r\n"
+        );
+
+        project.add_comment("Surveyed 2024");
+        assert!(project
+            .serialize()
+            .ends_with("#Fulsurf.dat;\r\n/Surveyed 2024\r\n"));
+    }
+
+    #[test]
+    fn fixed_stations_in_another_zone() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/zone_12_fixes.mak");
+        let mut project = Project::read(&sample_path).unwrap();
+        assert_eq!(project.utm_zone, Some(12));
+        assert_eq!(project.fixed_station_zone(), 12);
+        assert!(project.validate_utm_zone().is_ok());
+        assert!(project.serialize().contains("$12;\r\n"));
+
+        // A1 sits on the base location, written in zone 12 coordinates
+        let locations = project.fixed_station_locations();
+        let a1 = locations["A1"];
+        let base = project.base_location.east_north_elevation;
+        assert_float_eq!(a1.easting, base.easting, abs <= 0.002);
+        assert_float_eq!(a1.northing, base.northing, abs <= 0.002);
+        assert_float_eq!(a1.up, base.up, abs <= 0.001);
+
+        project.utm_zone = Some(15);
+        assert!(matches!(
+            project.validate_utm_zone(),
+            Err(Error::InvalidUtmZone(message)) if message.contains("zone 15")
+        ));
+        project.base_location.zone = 1;
+        project.utm_zone = Some(60);
+        assert!(project.validate_utm_zone().is_ok());
+        project.utm_zone = Some(12);
+        project.base_location.zone = 13;
+        project.load_survey_files().unwrap();
+    }
+
+    #[test]
+    fn fixed_stations_in_base_zone() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path).unwrap();
+        assert_eq!(project.utm_zone, Some(13));
+        let locations = project.fixed_station_locations();
+        assert_eq!(locations.len(), 4);
+        assert_eq!(
+            locations["A1"],
+            project.survey_files[0].project_stations[0]
+                .fix()
+                .unwrap()
+                .location()
+        );
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let input = include_str!("../../test_data/project_file_examples");
+        let (_, project) =
+            parser::parse_compass_project(PathBuf::from("examples.mak"), input).unwrap();
+        let serialized = project.serialize();
+        let (remaining, reparsed) =
+            parser::parse_compass_project(PathBuf::from("examples.mak"), &serialized).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(reparsed.base_location, project.base_location);
+        assert_eq!(reparsed.datum, project.datum);
+        assert_eq!(reparsed.survey_files, project.survey_files);
+
+        let cave3 = &reparsed.survey_files[7];
+        assert_eq!(cave3.file_path, PathBuf::from("cave3.dat"));
+        assert_eq!(cave3.folder, vec!["Folder-1", "Folder-2", "Folder-3"]);
+        let test2 = &reparsed.survey_files[4];
+        assert_eq!(
+            test2.project_stations[0].fix().unwrap().units(),
+            FixUnits::Meters
+        );
+        let wind2 = &reparsed.survey_files[12];
+        assert_eq!(wind2.file_path, PathBuf::from("WIND2.DAT"));
+        assert_eq!(wind2.comments[0], "from Wind1 to Wind2");
+        assert_eq!(wind2.project_stations.len(), 23);
+    }
+
+    #[test]
+    fn station_lookups() {
+        let input = include_str!("../../test_data/project_file_examples");
+        let (_, project) =
+            parser::parse_compass_project(PathBuf::from("examples.mak"), input).unwrap();
+        let file_names = |name| {
+            project
+                .files_referencing_station(name)
+                .iter()
+                .map(|survey_file| survey_file.file_path.to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(file_names("C41"), vec!["WIND2.DAT"]);
+        assert_eq!(
+            file_names("KX37R"),
+            vec!["WIND2.DAT", "WIND3.DAT", "WIND4.DAT", "WIND5.DAT"]
+        );
+        assert!(file_names("NOWHERE").is_empty());
+
+        let test1 = &project.survey_files[3];
+        let a1 = test1.station("A1").unwrap();
+        assert!(a1.is_fixed());
+        assert!(!a1.is_link());
+        assert_eq!(a1.fix().unwrap().units(), FixUnits::Feet);
+        assert_float_eq!(a1.fix().unwrap().location().up, 30.3 * 0.3048, abs <= 1e-9);
+        assert_eq!(test1.fixed_stations().count(), 1);
+        assert_eq!(test1.link_stations().count(), 0);
+
+        let wind6 = &project.survey_files[16];
+        let se202 = wind6.station("SE202").unwrap();
+        assert!(se202.is_link());
+        assert!(se202.fix().is_none());
+        assert_eq!(wind6.link_stations().count(), 1);
+    }
+
+    #[test]
+    fn folder_tree() {
+        let input = include_str!("../../test_data/project_file_examples");
+        let (_, project) =
+            parser::parse_compass_project(PathBuf::from("examples.mak"), input).unwrap();
+        let tree = project.folder_tree();
+        assert_eq!(tree.name, "");
+        assert_eq!(tree.folders.len(), 1);
+        assert_eq!(tree.survey_files.len(), 11);
+        assert_eq!(tree.all_survey_files().len(), project.survey_files.len());
+
+        let folder_1 = tree.folder(&["Folder-1"]).unwrap();
+        let names: Vec<_> = folder_1
+            .survey_files
+            .iter()
+            .map(|file| file.file_path.to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["cave1.dat", "cave6.dat"]);
+        let folder_2 = folder_1.folder(&["Folder-2"]).unwrap();
+        assert_eq!(folder_2.survey_files.len(), 2);
+        let folder_3 = tree.folder(&["Folder-1", "Folder-2", "Folder-3"]).unwrap();
+        assert_eq!(folder_3.survey_files.len(), 2);
+        assert_eq!(
+            folder_3.survey_files[0].folder,
+            vec!["Folder-1", "Folder-2", "Folder-3"]
+        );
+        assert_eq!(folder_1.all_survey_files().len(), 6);
+        assert!(tree.folder(&["Folder-2"]).is_none());
+    }
 }
@@ -0,0 +1,86 @@
+/// How Compass determines the declination applied to each survey
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeclinationMode {
+    /// I - Ignore declinations
+    Ignore,
+    /// E - Use the declinations entered in the survey files
+    Entered,
+    /// A - Calculate declinations automatically from the survey date and location
+    Auto,
+}
+
+/// Processing options from a `!` line in a project file
+///
+/// Each option is a single letter, upper case when enabled and lower case when disabled.
+/// Options which are not present in the project file are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectParameters {
+    /// G - Override the settings in the survey files with these parameters
+    pub override_settings: Option<bool>,
+    /// I, E or A - Which declinations to use
+    pub declination_mode: Option<DeclinationMode>,
+    /// V - Apply UTM convergence to azimuths
+    pub apply_convergence: Option<bool>,
+    /// C - Apply the instrument correction factors from the survey files
+    pub apply_corrections: Option<bool>,
+    /// O - Override the LRUD associations in the survey files
+    pub override_lruds: Option<bool>,
+    /// T - When overriding LRUD associations, associate LRUDs with the to station instead of the from station
+    pub lruds_at_to_station: Option<bool>,
+    /// S - Apply shot flags
+    pub apply_shot_flags: Option<bool>,
+    /// Any option characters which aren't understood, kept so they aren't lost when the project is written
+    pub unknown: String,
+}
+
+impl ProjectParameters {
+    pub(crate) fn from_options(options: &str) -> Self {
+        let mut parameters = ProjectParameters::default();
+        for option in options.chars() {
+            let enabled = option.is_ascii_uppercase();
+            match option {
+                'I' => parameters.declination_mode = Some(DeclinationMode::Ignore),
+                'E' => parameters.declination_mode = Some(DeclinationMode::Entered),
+                'A' => parameters.declination_mode = Some(DeclinationMode::Auto),
+                'G' | 'g' => parameters.override_settings = Some(enabled),
+                'V' | 'v' => parameters.apply_convergence = Some(enabled),
+                'C' | 'c' => parameters.apply_corrections = Some(enabled),
+                'O' | 'o' => parameters.override_lruds = Some(enabled),
+                'T' | 't' => parameters.lruds_at_to_station = Some(enabled),
+                'S' | 's' => parameters.apply_shot_flags = Some(enabled),
+                unknown => parameters.unknown.push(unknown),
+            }
+        }
+        parameters
+    }
+
+    /// Serialize the parameters to the options of a `!` line
+    #[must_use]
+    pub fn serialize(&self) -> String {
+        let mut result = String::new();
+        let mut push_option = |option: Option<bool>, letter: char| {
+            if let Some(enabled) = option {
+                result.push(if enabled {
+                    letter
+                } else {
+                    letter.to_ascii_lowercase()
+                });
+            }
+        };
+        push_option(self.override_settings, 'G');
+        push_option(self.apply_convergence, 'V');
+        push_option(self.apply_corrections, 'C');
+        push_option(self.override_lruds, 'O');
+        push_option(self.lruds_at_to_station, 'T');
+        push_option(self.apply_shot_flags, 'S');
+        if let Some(declination_mode) = self.declination_mode {
+            result.push(match declination_mode {
+                DeclinationMode::Ignore => 'I',
+                DeclinationMode::Entered => 'E',
+                DeclinationMode::Auto => 'A',
+            });
+        }
+        result.push_str(&self.unknown);
+        result
+    }
+}
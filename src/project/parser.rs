@@ -2,7 +2,7 @@ use nom::{
     branch::alt,
     bytes::complete::{tag, take_till, take_till1, take_until1},
     character::complete::{char, multispace0, u8},
-    combinator::value,
+    combinator::{opt, value, verify},
     multi::many0,
     IResult, Parser,
 };
@@ -10,8 +10,11 @@ use std::{marker::PhantomData, path::PathBuf};
 
 use crate::{
     parser_utils::{is_valid_station_name_char, parse_double, ws},
-    project::{Datum, Project, Station, SurveyFile, Unloaded, UtmLocation},
-    EastNorthElevation,
+    project::{
+        Datum, FixUnits, Project, ProjectParameters, Station, StationFix, SurveyFile, Unloaded,
+        UtmLocation,
+    },
+    EastNorthElevation, Hemisphere,
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -20,8 +23,11 @@ enum ProjectElement {
     CarriageReturn,
     Comment(String),
     Datum(Datum),
+    EndOfFile,
     LineFeed,
+    Convergence(bool),
     File(SurveyFile<Unloaded>),
+    Parameters(ProjectParameters),
     PushFolder(String),
     PopFolder,
     UtmZone(u8),
@@ -38,11 +44,23 @@ fn parse_triple_double(input: &str) -> IResult<&str, (f64, f64, f64)> {
     Ok((input, (val_0, val_1, val_2)))
 }
 
+/// UTM zones are written as negative numbers in the southern hemisphere
+fn parse_zone(input: &str) -> IResult<&str, (u8, Hemisphere)> {
+    let (input, south) = opt(char('-'))(input)?;
+    let (input, zone) = verify(u8, |zone| (1..=60).contains(zone))(input)?;
+    let hemisphere = if south.is_some() {
+        Hemisphere::South
+    } else {
+        Hemisphere::North
+    };
+    Ok((input, (zone, hemisphere)))
+}
+
 fn parse_base_location(input: &str) -> IResult<&str, ProjectElement> {
     let (input, _) = char('@')(input)?;
     let (input, (east, north, elevation)) = parse_triple_double(input)?;
     let (input, _) = char(',')(input)?;
-    let (input, zone) = u8(input)?;
+    let (input, (zone, hemisphere)) = parse_zone(input)?;
     let (input, _) = char(',')(input)?;
     let (input, convergence_angle) = parse_double(input)?;
     let (input, _) = char(';')(input)?;
@@ -52,6 +70,7 @@ fn parse_base_location(input: &str) -> IResult<&str, ProjectElement> {
         ProjectElement::BaseLocation(UtmLocation {
             east_north_elevation,
             zone,
+            hemisphere,
             convergence_angle,
         }),
     ))
@@ -113,44 +132,58 @@ fn is_terminator(c: char) -> bool {
     c == ';'
 }
 
-fn parse_station_fix(input: &str) -> IResult<&str, EastNorthElevation> {
+fn parse_station_fix(input: &str) -> IResult<&str, StationFix> {
     let (input, _) = char('[')(input)?;
     // Eat the whitespace before and after the unit tag
     let (input, unit_char) = ws(alt((char('m'), char('M'), char('f'), char('F')))).parse(input)?;
     let (input, _) = char(',')(input)?;
     let (input, (east, north, elevation)) = parse_triple_double(input)?;
     let (input, _) = char(']')(input)?;
-    let ene = match unit_char.to_ascii_lowercase() {
-        'm' => EastNorthElevation::from_meters(east, north, elevation),
-        'f' => EastNorthElevation::from_feet(east, north, elevation),
+    let fix = match unit_char.to_ascii_lowercase() {
+        'm' => StationFix::new(
+            EastNorthElevation::from_meters(east, north, elevation),
+            FixUnits::Meters,
+        ),
+        'f' => StationFix::new(
+            EastNorthElevation::from_feet(east, north, elevation),
+            FixUnits::Feet,
+        ),
         _ => panic!("invalid unit tag"),
     };
-    Ok((input, ene))
+    Ok((input, fix))
+}
+
+/// Comments can appear between any of the stations in a file entry
+fn parse_entry_comments(input: &str) -> IResult<&str, Vec<String>> {
+    let (input, comments) = many0(parse_comment)(input)?;
+    let comments = comments
+        .into_iter()
+        .filter_map(|comment| match comment {
+            ProjectElement::Comment(comment) if !comment.trim().is_empty() => {
+                Some(comment.trim().to_string())
+            }
+            _ => None,
+        })
+        .collect();
+    Ok((input, comments))
 }
 
 // Each station is a comma separated list of station name and optional fixed location
-fn parse_station(input: &str) -> IResult<&str, Station> {
+fn parse_station(input: &str) -> IResult<&str, (Station, Vec<String>)> {
     let (input, _) = char(',')(input)?;
-    let (input, _) = many0(parse_comment)(input)?;
+    let (input, comments) = parse_entry_comments(input)?;
     let (input, station_name) = ws(take_till(|c| !is_valid_station_name_char(c))).parse(input)?;
-    let station_fixed = parse_station_fix(input);
-    if let Ok((input, fix)) = station_fixed {
-        Ok((
-            input,
-            Station {
-                name: station_name.to_string(),
-                location: Some(fix),
-            },
-        ))
-    } else {
-        Ok((
-            input,
-            Station {
-                name: station_name.to_string(),
-                location: None,
+    let (input, fix) = opt(parse_station_fix)(input)?;
+    Ok((
+        input,
+        (
+            match fix {
+                Some(fix) => Station::fixed(station_name, fix),
+                None => Station::link(station_name),
             },
-        ))
-    }
+            comments,
+        ),
+    ))
 }
 
 fn parse_project_file(input: &str) -> IResult<&str, ProjectElement> {
@@ -158,13 +191,27 @@ fn parse_project_file(input: &str) -> IResult<&str, ProjectElement> {
     let (input, file_path) =
         ws(take_till1(|c| is_separator(c) || is_terminator(c))).parse(input)?;
     let (input, stations) = many0(parse_station)(input)?;
+    let (input, trailing_comments) = parse_entry_comments(input)?;
     let (input, _) = char(';')(input)?;
     let file_path = PathBuf::from(file_path);
+    let mut comments = Vec::new();
+    let mut project_stations = Vec::new();
+    for (station, station_comments) in stations {
+        comments.extend(station_comments);
+        // A trailing comma with nothing after it shouldn't produce a station
+        if !station.name().is_empty() {
+            project_stations.push(station);
+        }
+    }
+    comments.extend(trailing_comments);
     Ok((
         input,
         ProjectElement::File(SurveyFile {
             file_path,
-            project_stations: stations,
+            project_stations,
+            folder: Vec::new(),
+            comments,
+            parameters: None,
             surveys: vec![],
             state: PhantomData::<Unloaded>,
         }),
@@ -186,11 +233,28 @@ fn parse_pop_folder(input: &str) -> IResult<&str, ProjectElement> {
 
 fn parse_utm_zone(input: &str) -> IResult<&str, ProjectElement> {
     let (input, _) = tag("$")(input)?;
-    let (input, zone) = u8(input)?;
+    // The hemisphere always matches the base location's
+    let (input, (zone, _)) = parse_zone(input)?;
     let (input, _) = char(';')(input)?;
     Ok((input, ProjectElement::UtmZone(zone)))
 }
 
+fn parse_project_parameters(input: &str) -> IResult<&str, ProjectElement> {
+    let (input, _) = char('!')(input)?;
+    let (input, options) = ws(take_till(is_terminator)).parse(input)?;
+    let (input, _) = char(';')(input)?;
+    Ok((
+        input,
+        ProjectElement::Parameters(ProjectParameters::from_options(options)),
+    ))
+}
+
+fn parse_convergence(input: &str) -> IResult<&str, ProjectElement> {
+    let (input, enabled) = alt((value(true, char('%')), value(false, char('*'))))(input)?;
+    let (input, _) = opt(char(';'))(input)?;
+    Ok((input, ProjectElement::Convergence(enabled)))
+}
+
 fn parse_whitespace(input: &str) -> IResult<&str, ProjectElement> {
     let (input, _) = take_till1(|c: char| !c.is_whitespace())(input)?;
     Ok((input, ProjectElement::Whitespace))
@@ -203,10 +267,14 @@ fn parse_project_element(input: &str) -> IResult<&str, ProjectElement> {
         parse_comment,
         parse_datum,
         value(ProjectElement::LineFeed, char('\n')),
+        // DOS era Compass terminates files with a Ctrl-Z
+        value(ProjectElement::EndOfFile, char('\x1a')),
         parse_project_file,
         parse_push_folder,
         parse_pop_folder,
         parse_utm_zone,
+        parse_project_parameters,
+        parse_convergence,
         parse_whitespace,
     ))(input)
 }
@@ -215,19 +283,50 @@ pub fn parse_compass_project(file_path: PathBuf, input: &str) -> IResult<&str, P
     let mut input = input;
     let mut base_location: Option<UtmLocation> = None;
     let mut datum: Option<Datum> = None;
+    let mut parameters: Option<ProjectParameters> = None;
+    let mut file_parameters: Option<ProjectParameters> = None;
+    let mut utm_convergence: Option<bool> = None;
+    let mut utm_zone: Option<u8> = None;
     let mut survey_data_files: Vec<SurveyFile<Unloaded>> = Vec::new();
     let mut folders = Vec::new();
+    let mut comments = Vec::new();
 
-    while let Ok((munched, element)) = parse_project_element(input) {
+    while !input.is_empty() {
+        let Ok((munched, element)) = parse_project_element(input) else {
+            // Stopping here would silently drop the rest of the project
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Alt,
+            )));
+        };
         input = munched;
         match element {
             ProjectElement::BaseLocation(parsed_base_location) => {
                 base_location = Some(parsed_base_location);
             }
+            ProjectElement::Comment(comment) => comments.push((survey_data_files.len(), comment)),
+            ProjectElement::Convergence(enabled) => utm_convergence = Some(enabled),
             ProjectElement::Datum(parsed_datum) => datum = Some(parsed_datum),
-            ProjectElement::File(file_info) => survey_data_files.push(file_info),
+            ProjectElement::File(mut file_info) => {
+                file_info.folder.clone_from(&folders);
+                file_info.parameters.clone_from(&file_parameters);
+                survey_data_files.push(file_info);
+            }
+            // Parameters before any survey files apply to the whole project,
+            // later ones override the project parameters for the files that follow,
+            // until a line repeating the project parameters restores them
+            ProjectElement::Parameters(parsed_parameters) => {
+                if survey_data_files.is_empty() {
+                    parameters = Some(parsed_parameters);
+                } else if parameters.clone().unwrap_or_default() == parsed_parameters {
+                    file_parameters = None;
+                } else {
+                    file_parameters = Some(parsed_parameters);
+                }
+            }
             ProjectElement::PushFolder(folder) => folders.push(folder),
             ProjectElement::PopFolder => _ = folders.pop(),
+            ProjectElement::UtmZone(zone) => utm_zone = Some(zone),
 
             _ => (),
         }
@@ -240,7 +339,10 @@ pub fn parse_compass_project(file_path: PathBuf, input: &str) -> IResult<&str, P
                 base_location,
                 datum,
                 survey_files: survey_data_files,
-                utm_zone: None,
+                comments,
+                utm_zone,
+                parameters,
+                utm_convergence,
                 state: PhantomData::<Unloaded>,
             },
         ))
@@ -254,6 +356,7 @@ mod tests {
     use float_eq::assert_float_eq;
 
     use super::*;
+    use crate::project::DeclinationMode;
     #[test]
     fn parse_format_examples() {
         const FILE_PATH: &str = "../../test_data/project_file_examples";
@@ -293,4 +396,107 @@ mod tests {
         assert!(project.datum == Datum::NorthAmerican1983);
         assert!(!project.survey_files.is_empty());
     }
+
+    #[test]
+    fn parse_parameters_and_convergence() {
+        let input = "@398315.500,4483735.300,3048.000,13,0.780;
+&North American 1983;
+%;
+!GvCOtS;
+#FIRST.DAT;
+!gE;
+#SECOND.DAT;
+#THIRD.DAT;
+";
+        let (_, project) = parse_compass_project(PathBuf::from("test.mak"), input).unwrap();
+        assert_eq!(project.utm_convergence, Some(true));
+        let parameters = project.parameters.as_ref().unwrap();
+        assert_eq!(parameters.override_settings, Some(true));
+        assert_eq!(parameters.apply_convergence, Some(false));
+        assert_eq!(parameters.apply_corrections, Some(true));
+        assert_eq!(parameters.override_lruds, Some(true));
+        assert_eq!(parameters.lruds_at_to_station, Some(false));
+        assert_eq!(parameters.apply_shot_flags, Some(true));
+        assert_eq!(parameters.declination_mode, None);
+        assert_eq!(parameters.serialize(), "GvCOtS");
+
+        assert_eq!(project.survey_files[0].parameters, None);
+        let file_parameters = project.survey_files[1].parameters.as_ref().unwrap();
+        assert_eq!(file_parameters.override_settings, Some(false));
+        assert_eq!(
+            file_parameters.declination_mode,
+            Some(DeclinationMode::Entered)
+        );
+        assert_eq!(
+            project.survey_files[2].parameters,
+            project.survey_files[1].parameters
+        );
+
+        let serialized = project.serialize();
+        let (_, reparsed) = parse_compass_project(PathBuf::from("test.mak"), &serialized).unwrap();
+        assert_eq!(reparsed.utm_convergence, project.utm_convergence);
+        assert_eq!(reparsed.parameters, project.parameters);
+        assert_eq!(reparsed.survey_files, project.survey_files);
+
+        let (_, project) =
+            parse_compass_project(PathBuf::from("test.mak"), &input.replace("%;", "*;")).unwrap();
+        assert_eq!(project.utm_convergence, Some(false));
+    }
+
+    #[test]
+    fn file_parameters_restored() {
+        let input = "@398315.500,4483735.300,3048.000,13,0.780;
+&North American 1983;
+#FIRST.DAT;
+!gE;
+#SECOND.DAT;
+#THIRD.DAT;
+";
+        let (_, mut project) = parse_compass_project(PathBuf::from("test.mak"), input).unwrap();
+        project.survey_files[2].parameters = None;
+        let serialized = project.serialize();
+        assert!(serialized.contains("!gE;\r\n#SECOND.DAT;\r\n!;\r\n#THIRD.DAT;"));
+        let (_, reparsed) = parse_compass_project(PathBuf::from("test.mak"), &serialized).unwrap();
+        assert_eq!(reparsed.parameters, None);
+        assert_eq!(reparsed.survey_files, project.survey_files);
+    }
+
+    #[test]
+    fn unknown_element_is_an_error() {
+        let input = "@398315.500,4483735.300,3048.000,13,0.780;
+&North American 1983;
+#FIRST.DAT;
+^MYSTERY;
+#SECOND.DAT;
+";
+        let result = parse_compass_project(PathBuf::from("test.mak"), input);
+        assert!(matches!(result, Err(nom::Err::Failure(e)) if e.input.starts_with("^MYSTERY;")));
+    }
+
+    #[test]
+    fn utm_zone_must_exist() {
+        let input = "@398315.500,4483735.300,3048.000,13,0.780;
+&North American 1983;
+$61;
+#FIRST.DAT;
+";
+        let result = parse_compass_project(PathBuf::from("test.mak"), input);
+        assert!(matches!(result, Err(nom::Err::Failure(e)) if e.input.starts_with("$61;")));
+    }
+
+    #[test]
+    fn southern_hemisphere_zone() {
+        let input = "@334900.000,6297400.000,120.000,-55,-0.950;
+&Wgs 1984;
+$-55;
+#FIRST.DAT;
+";
+        let (_, project) = parse_compass_project(PathBuf::from("test.mak"), input).unwrap();
+        assert_eq!(project.base_location.zone, 55);
+        assert_eq!(project.base_location.hemisphere, Hemisphere::South);
+        assert_eq!(project.utm_zone, Some(55));
+        assert!(project
+            .serialize()
+            .starts_with("@334900.000,6297400.000,120.000,-55,-0.950;\r\n&Wgs 1984;\r\n$-55;\r\n"));
+    }
 }
@@ -0,0 +1,160 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{Error, Survey};
+
+use super::{Loaded, Project};
+
+/// A file which has been written next to its destination, waiting to be moved into place
+struct PendingFile {
+    temporary: PathBuf,
+    destination: PathBuf,
+    /// Copy of the file the destination held before it was replaced, kept until the whole save succeeds
+    original: Option<PathBuf>,
+}
+
+/// `path` with `suffix` appended to its file name, `Fulford.dat` becomes `Fulford.dat.bak`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+fn invalid_input(message: String) -> Error {
+    Error::CouldntWriteFile(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+/// Survey file paths must stay inside the directory the project is saved to
+fn check_survey_file_path(path: &Path) -> Result<(), Error> {
+    let inside = path.is_relative()
+        && path.components().count() > 0
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if inside {
+        Ok(())
+    } else {
+        Err(invalid_input(format!(
+            "{} is outside the project directory",
+            path.display()
+        )))
+    }
+}
+
+fn write_temporary(destination: PathBuf, contents: &str) -> Result<PendingFile, Error> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(Error::CouldntWriteFile)?;
+    }
+    let temporary = with_suffix(&destination, ".tmp");
+    let mut file = File::create(&temporary).map_err(Error::CouldntWriteFile)?;
+    let written = file
+        .write_all(contents.as_bytes())
+        .and_then(|()| file.sync_all());
+    if let Err(e) = written {
+        _ = fs::remove_file(&temporary);
+        return Err(Error::CouldntWriteFile(e));
+    }
+    Ok(PendingFile {
+        temporary,
+        destination,
+        original: None,
+    })
+}
+
+/// Move a written file into place, copying aside the file it replaces so the save can be undone
+fn commit(pending: &mut PendingFile) -> Result<(), Error> {
+    if pending.destination.exists() {
+        let original = with_suffix(&pending.destination, ".orig");
+        fs::copy(&pending.destination, &original).map_err(Error::CouldntWriteFile)?;
+        pending.original = Some(original);
+    }
+    fs::rename(&pending.temporary, &pending.destination).map_err(Error::CouldntWriteFile)
+}
+
+/// Put back whatever a committed file replaced
+fn roll_back(pending: &PendingFile) {
+    match &pending.original {
+        Some(original) => _ = fs::rename(original, &pending.destination),
+        None => _ = fs::remove_file(&pending.destination),
+    }
+}
+
+/// Clean up after a file which failed to commit, or was never committed
+fn discard(pending: &PendingFile) {
+    _ = fs::remove_file(&pending.temporary);
+    if let Some(original) = &pending.original {
+        _ = fs::remove_file(original);
+    }
+}
+
+/// Keep the copy of what a committed file replaced as its backup, or remove it
+fn finish(pending: &PendingFile, keep_backups: bool) -> Result<(), Error> {
+    let Some(original) = &pending.original else {
+        return Ok(());
+    };
+    if keep_backups {
+        fs::rename(original, with_suffix(&pending.destination, ".bak"))
+    } else {
+        fs::remove_file(original)
+    }
+    .map_err(Error::CouldntWriteFile)
+}
+
+pub(crate) fn save_project(
+    project: &Project<Loaded>,
+    directory: &Path,
+    keep_backups: bool,
+) -> Result<(), Error> {
+    let project_file_name = project.file_path.file_name().ok_or_else(|| {
+        invalid_input(format!("{} has no file name", project.file_path.display()))
+    })?;
+    let mut contents = vec![(directory.join(project_file_name), project.serialize())];
+    for survey_file in &project.survey_files {
+        check_survey_file_path(&survey_file.file_path)?;
+        let destination = directory.join(&survey_file.file_path);
+        // Files saved to the same place would overwrite each other's temporary files
+        if contents
+            .iter()
+            .any(|(existing, _)| *existing == destination)
+        {
+            return Err(invalid_input(format!(
+                "{} is saved more than once",
+                destination.display()
+            )));
+        }
+        contents.push((
+            destination,
+            Survey::serialize_dat_file(survey_file.surveys()),
+        ));
+    }
+
+    // Every file is written out before any of them replace what is on disk,
+    // and the files already replaced are put back if moving one into place fails,
+    // so a failed save leaves the existing project as it was
+    let mut pending = Vec::with_capacity(contents.len());
+    for (destination, contents) in contents {
+        match write_temporary(destination, &contents) {
+            Ok(file) => pending.push(file),
+            Err(e) => {
+                pending.iter().for_each(discard);
+                return Err(e);
+            }
+        }
+    }
+    for index in 0..pending.len() {
+        if let Err(e) = commit(&mut pending[index]) {
+            pending[..index].iter().rev().for_each(roll_back);
+            pending[index..].iter().for_each(discard);
+            return Err(e);
+        }
+    }
+    // Everything is committed by now, so tidy up after every file before reporting the first failure
+    pending
+        .iter()
+        .map(|file| finish(file, keep_backups))
+        .fold(Ok(()), Result::and)
+}
@@ -0,0 +1,155 @@
+use std::fmt::Write as _;
+
+use crate::{common_types::FEET_TO_METERS, Hemisphere};
+
+use super::{Datum, FixUnits, Project, ProjectParameters, Station};
+
+const LINE_ENDING: &str = "\r\n";
+
+fn datum_name(datum: Datum) -> &'static str {
+    match datum {
+        Datum::Adindan => "Adindan",
+        Datum::Arc1950 => "Arc 1950",
+        Datum::Arc1960 => "Arc 1960",
+        Datum::Australian1966 => "Australian 1966",
+        Datum::Australian1984 => "Australian 1984",
+        Datum::CampAreaAstro => "Camp Area Astro",
+        Datum::Cape => "Cape",
+        Datum::European1950 => "European 1950",
+        Datum::European1979 => "European 1979",
+        Datum::Geodetic1949 => "Geodetic 1949",
+        Datum::HongKong1963 => "HongKong 1963",
+        Datum::HuTzuShan => "HuTzuShan",
+        Datum::Indian => "Indian",
+        Datum::NorthAmerican1927 => "North American 1927",
+        Datum::NorthAmerican1983 => "North American 1983",
+        Datum::Oman => "Oman",
+        Datum::OrdinanceSurvey1936 => "Ordinance Survey 1936",
+        Datum::Pulkovo1942 => "Pulkovo 1942",
+        Datum::SouthAmerican1956 => "South American 1956",
+        Datum::SouthAmerican1969 => "South American 1969",
+        Datum::Tokyo => "Tokyo",
+        Datum::Wgs1972 => "Wgs 1972",
+        Datum::Wgs1984 => "Wgs 1984",
+    }
+}
+
+fn station(station: &Station) -> String {
+    match station.fix() {
+        None => station.name().to_string(),
+        Some(fix) => {
+            let location = fix.location();
+            let (unit_char, scale) = match fix.units() {
+                FixUnits::Feet => ('F', 1.0 / FEET_TO_METERS),
+                FixUnits::Meters => ('M', 1.0),
+            };
+            format!(
+                "{}[{unit_char},{:.3},{:.3},{:.3}]",
+                station.name(),
+                location.easting * scale,
+                location.northing * scale,
+                location.up * scale
+            )
+        }
+    }
+}
+
+/// Compass marks southern hemisphere zones by writing them as negative numbers
+fn signed_zone(zone: u8, hemisphere: Hemisphere) -> i16 {
+    match hemisphere {
+        Hemisphere::North => i16::from(zone),
+        Hemisphere::South => -i16::from(zone),
+    }
+}
+
+pub(crate) fn serialize_project<S>(project: &Project<S>) -> String {
+    let mut result = String::new();
+    let base = &project.base_location;
+    let _ = write!(
+        result,
+        "@{:.3},{:.3},{:.3},{},{:.3};{LINE_ENDING}",
+        base.east_north_elevation.easting,
+        base.east_north_elevation.northing,
+        base.east_north_elevation.up,
+        signed_zone(base.zone, base.hemisphere),
+        base.convergence_angle
+    );
+    let _ = write!(result, "&{};{LINE_ENDING}", datum_name(project.datum));
+    if let Some(utm_zone) = project.utm_zone {
+        let _ = write!(
+            result,
+            "${};{LINE_ENDING}",
+            signed_zone(utm_zone, base.hemisphere)
+        );
+    }
+    if let Some(enabled) = project.utm_convergence {
+        let _ = write!(result, "{};{LINE_ENDING}", if enabled { '%' } else { '*' });
+    }
+    if let Some(parameters) = &project.parameters {
+        let _ = write!(result, "!{};{LINE_ENDING}", parameters.serialize());
+    }
+
+    let mut comments = project.comments.iter().peekable();
+    let mut open_folders: &[String] = &[];
+    let mut file_parameters = None;
+    for (index, survey_file) in project.survey_files.iter().enumerate() {
+        while let Some((_, comment)) = comments.next_if(|(position, _)| *position <= index) {
+            let _ = write!(
+                result,
+                "{}/{comment}{LINE_ENDING}",
+                indent(open_folders.len())
+            );
+        }
+        if survey_file.parameters != file_parameters {
+            file_parameters.clone_from(&survey_file.parameters);
+            // Once overridden, the project parameters can only be restored by repeating them,
+            // an empty line restores a project without any
+            let parameters = file_parameters
+                .as_ref()
+                .or(project.parameters.as_ref())
+                .map(ProjectParameters::serialize)
+                .unwrap_or_default();
+            let _ = write!(result, "!{parameters};{LINE_ENDING}");
+        }
+        let common = open_folders
+            .iter()
+            .zip(&survey_file.folder)
+            .take_while(|(open, wanted)| open == wanted)
+            .count();
+        for depth in (common..open_folders.len()).rev() {
+            let _ = write!(result, "{}];{LINE_ENDING}", indent(depth));
+        }
+        for (depth, folder) in survey_file.folder.iter().enumerate().skip(common) {
+            let _ = write!(result, "{}[{folder};{LINE_ENDING}", indent(depth));
+        }
+        open_folders = &survey_file.folder;
+
+        let indent = indent(open_folders.len());
+        let _ = write!(result, "{indent}#{}", survey_file.file_path.display());
+        if survey_file.comments.is_empty() && survey_file.project_stations.is_empty() {
+            let _ = write!(result, ";{LINE_ENDING}");
+            continue;
+        }
+        let _ = write!(result, ",{LINE_ENDING}");
+        for comment in &survey_file.comments {
+            let _ = write!(result, "{indent}  /{comment}{LINE_ENDING}");
+        }
+        let stations: Vec<String> = survey_file.project_stations.iter().map(station).collect();
+        let _ = write!(
+            result,
+            "{indent}  {};{LINE_ENDING}",
+            stations.join(&format!(",{LINE_ENDING}{indent}  "))
+        );
+    }
+    for depth in (0..open_folders.len()).rev() {
+        let _ = write!(result, "{}];{LINE_ENDING}", indent(depth));
+    }
+    for (_, comment) in comments {
+        let _ = write!(result, "/{comment}{LINE_ENDING}");
+    }
+    result
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
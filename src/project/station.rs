@@ -0,0 +1,97 @@
+use crate::EastNorthElevation;
+
+/// Units a fixed station's coordinates are written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixUnits {
+    Feet,
+    Meters,
+}
+
+/// Coordinates a fixed station is held at
+#[derive(Clone, Debug, PartialEq)]
+pub struct StationFix {
+    /// Always stored in meters, regardless of the units used in the project file
+    location: EastNorthElevation,
+    units: FixUnits,
+}
+
+impl StationFix {
+    /// Create a fix at `location`, which is in meters
+    /// `units` only controls how the coordinates are written to the project file
+    #[must_use]
+    pub fn new(location: EastNorthElevation, units: FixUnits) -> Self {
+        Self { location, units }
+    }
+
+    /// The fixed coordinates in meters, in the project's fixed station UTM zone
+    #[must_use]
+    pub fn location(&self) -> EastNorthElevation {
+        self.location
+    }
+
+    /// The units the coordinates are written in in the project file
+    #[must_use]
+    pub fn units(&self) -> FixUnits {
+        self.units
+    }
+}
+
+/// A station listed in a survey file's entry in the project file
+///
+/// Stations without coordinates are link stations, which tie the survey file
+/// to the other files that list the same station.
+/// Stations with coordinates are fixed stations, which hold the survey to a known location.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Station {
+    name: String,
+    fix: Option<StationFix>,
+}
+
+impl Station {
+    /// Create a link station
+    #[must_use]
+    pub fn link(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            fix: None,
+        }
+    }
+
+    /// Create a station fixed at known coordinates
+    #[must_use]
+    pub fn fixed(name: impl Into<String>, fix: StationFix) -> Self {
+        Self {
+            name: name.into(),
+            fix: Some(fix),
+        }
+    }
+
+    /// The station's name, as used in the survey data
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The station's fixed coordinates, `None` for link stations
+    #[must_use]
+    pub fn fix(&self) -> Option<&StationFix> {
+        self.fix.as_ref()
+    }
+
+    /// Fix the station at known coordinates, or make it a link station again with `None`
+    pub fn set_fix(&mut self, fix: Option<StationFix>) {
+        self.fix = fix;
+    }
+
+    /// Whether the station is held at known coordinates
+    #[must_use]
+    pub fn is_fixed(&self) -> bool {
+        self.fix.is_some()
+    }
+
+    /// Whether the station only links survey files, without coordinates of its own
+    #[must_use]
+    pub fn is_link(&self) -> bool {
+        self.fix.is_none()
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+
+use crate::{project::Loaded, EastNorthElevation, Project, Survey};
+
+use super::{
+    project_anchors, project_legs, survey_legs, to_owned_names, Corrections, Leg, Traverse,
+};
+
+/// Legs shorter than this many meters are weighted as if they were this long,
+/// so zero length shots don't make the adjustment singular
+const MINIMUM_WEIGHTING_LENGTH: f64 = 0.01;
+
+/// An independent loop in the shot network, and how far it fails to close
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopClosure {
+    /// Stations around the loop in order, the loop is closed by a shot from the last station back to the first
+    pub stations: Vec<String>,
+    /// Total length of the shots around the loop, in meters
+    pub length: f64,
+    /// Where the traverse around the loop ends up relative to where it started, in meters
+    pub misclosure: EastNorthElevation,
+}
+
+impl LoopClosure {
+    /// Length of the misclosure vector, in meters
+    #[must_use]
+    pub fn misclosure_distance(&self) -> f64 {
+        let EastNorthElevation {
+            easting,
+            northing,
+            up,
+        } = self.misclosure;
+        (easting * easting + northing * northing + up * up).sqrt()
+    }
+
+    /// Misclosure as a fraction of the loop length, Compass reports this as 1 part in `1 / ratio`
+    #[must_use]
+    pub fn misclosure_ratio(&self) -> f64 {
+        if self.length > 0.0 {
+            self.misclosure_distance() / self.length
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Station coordinates before and after closing loops
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdjustedCoordinates {
+    /// Coordinates from the traverse, before any adjustment
+    pub raw: HashMap<String, EastNorthElevation>,
+    /// Coordinates after distributing loop misclosures with a weighted least squares adjustment
+    pub adjusted: HashMap<String, EastNorthElevation>,
+    /// The independent loops found in the shot network
+    pub loops: Vec<LoopClosure>,
+}
+
+/// The stations from `station` back to the origin of its traverse, with the length of each leg walked
+fn path_to_origin<'a>(
+    legs: &[Leg<'a>],
+    traverse: &Traverse<'a>,
+    station: &'a str,
+) -> Vec<(&'a str, f64)> {
+    let mut path = vec![(station, 0.0)];
+    let mut current = station;
+    while let Some(&index) = traverse.reached_by.get(current) {
+        let leg = &legs[index];
+        current = if leg.to == current { leg.from } else { leg.to };
+        path.push((current, leg.length()));
+    }
+    path
+}
+
+/// Every leg the traverse didn't need closes exactly one independent loop
+fn find_loops<'a>(legs: &[Leg<'a>], traverse: &Traverse<'a>) -> Vec<LoopClosure> {
+    let tree_legs: Vec<usize> = traverse.reached_by.values().copied().collect();
+    let mut is_tree_leg = vec![false; legs.len()];
+    for index in tree_legs {
+        is_tree_leg[index] = true;
+    }
+    let mut loops = Vec::new();
+    for (index, leg) in legs.iter().enumerate() {
+        if is_tree_leg[index] || leg.from == leg.to {
+            continue;
+        }
+        let from_path = path_to_origin(legs, traverse, leg.from);
+        let to_path = path_to_origin(legs, traverse, leg.to);
+        // Legs between stations positioned from different origins join fixed stations rather than closing a loop
+        if from_path.last().map(|(station, _)| station)
+            != to_path.last().map(|(station, _)| station)
+        {
+            continue;
+        }
+        let shared = from_path
+            .iter()
+            .rev()
+            .zip(to_path.iter().rev())
+            .take_while(|(from, to)| from.0 == to.0)
+            .count();
+        // Both paths end with the stations they share, the first of which is where they meet
+        let (from_junction, to_junction) = (from_path.len() - shared, to_path.len() - shared);
+        let walked = |path: &[(&str, f64)]| path.iter().map(|(_, length)| length).sum::<f64>();
+        let length = leg.length()
+            + walked(&from_path[1..=from_junction])
+            + walked(&to_path[1..=to_junction]);
+        let stations = to_path[..=to_junction]
+            .iter()
+            .chain(from_path[..from_junction].iter().rev())
+            .map(|(station, _)| (*station).to_string())
+            .collect();
+        loops.push(LoopClosure {
+            stations,
+            length,
+            misclosure: traverse.positions[leg.from] + leg.vector - traverse.positions[leg.to],
+        });
+    }
+    loops
+}
+
+/// Find the group each station belongs to, where shots flagged C hold stations rigidly together
+/// A group never holds more than one origin, a C shot which would join two is adjusted like any other.
+struct RigidGroups<'a> {
+    group: HashMap<&'a str, usize>,
+    /// Offset of each station from its group's reference point
+    offset: HashMap<&'a str, EastNorthElevation>,
+    count: usize,
+}
+
+impl<'a> RigidGroups<'a> {
+    fn new(legs: &[Leg<'a>], traverse: &Traverse<'a>) -> Self {
+        let mut held: HashMap<&str, Vec<(&str, EastNorthElevation)>> = HashMap::new();
+        for leg in legs.iter().filter(|leg| leg.do_not_adjust) {
+            held.entry(leg.from).or_default().push((leg.to, leg.vector));
+            held.entry(leg.to)
+                .or_default()
+                .push((leg.from, -leg.vector));
+        }
+        let mut groups = Self {
+            group: HashMap::new(),
+            offset: HashMap::new(),
+            count: 0,
+        };
+        // Origins come first, so each group's reference point is its origin when it has one
+        let stations = traverse
+            .origins
+            .iter()
+            .copied()
+            .chain(legs.iter().flat_map(|leg| [leg.from, leg.to]));
+        for station in stations {
+            if groups.group.contains_key(station) {
+                continue;
+            }
+            let group = groups.count;
+            groups.count += 1;
+            groups.group.insert(station, group);
+            groups
+                .offset
+                .insert(station, EastNorthElevation::from_meters(0.0, 0.0, 0.0));
+            let mut stack = vec![station];
+            while let Some(current) = stack.pop() {
+                let offset = groups.offset[current];
+                for &(neighbour, vector) in held.get(current).into_iter().flatten() {
+                    if !groups.group.contains_key(neighbour)
+                        && !traverse.origins.contains(&neighbour)
+                    {
+                        groups.group.insert(neighbour, group);
+                        groups.offset.insert(neighbour, offset + vector);
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        groups
+    }
+}
+
+/// A sparse symmetric positive definite system of normal equations
+struct NormalEquations {
+    diagonal: Vec<f64>,
+    off_diagonal: Vec<Vec<(usize, f64)>>,
+    right_hand_side: Vec<[f64; 3]>,
+}
+
+impl NormalEquations {
+    fn new(unknowns: usize) -> Self {
+        Self {
+            diagonal: vec![0.0; unknowns],
+            off_diagonal: vec![Vec::new(); unknowns],
+            right_hand_side: vec![[0.0; 3]; unknowns],
+        }
+    }
+
+    fn add_to_right_hand_side(&mut self, unknown: usize, value: EastNorthElevation, weight: f64) {
+        for (total, value) in self.right_hand_side[unknown].iter_mut().zip(axes(value)) {
+            *total += weight * value;
+        }
+    }
+
+    fn multiply(&self, x: &[f64]) -> Vec<f64> {
+        self.diagonal
+            .iter()
+            .zip(&self.off_diagonal)
+            .zip(x)
+            .map(|((diagonal, row), value)| {
+                diagonal * value + row.iter().map(|(column, a)| a * x[*column]).sum::<f64>()
+            })
+            .collect()
+    }
+
+    /// Solve one axis with conjugate gradients, preconditioned by the diagonal
+    fn solve(&self, axis: usize, mut x: Vec<f64>) -> Vec<f64> {
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+        let b: Vec<f64> = self.right_hand_side.iter().map(|b| b[axis]).collect();
+        let tolerance = 1e-14 * dot(&b, &b).max(1.0);
+        let mut residual: Vec<f64> = b
+            .iter()
+            .zip(self.multiply(&x))
+            .map(|(b, ax)| b - ax)
+            .collect();
+        let precondition = |r: &[f64]| -> Vec<f64> {
+            r.iter()
+                .zip(&self.diagonal)
+                .map(|(r, diagonal)| r / diagonal)
+                .collect()
+        };
+        let mut z = precondition(&residual);
+        let mut direction = z.clone();
+        let mut rz = dot(&residual, &z);
+        for _ in 0..(10 * x.len() + 100) {
+            if dot(&residual, &residual) <= tolerance {
+                break;
+            }
+            let a_direction = self.multiply(&direction);
+            let step = rz / dot(&direction, &a_direction);
+            for i in 0..x.len() {
+                x[i] += step * direction[i];
+                residual[i] -= step * a_direction[i];
+            }
+            z = precondition(&residual);
+            let next_rz = dot(&residual, &z);
+            for i in 0..x.len() {
+                direction[i] = z[i] + next_rz / rz * direction[i];
+            }
+            rz = next_rz;
+        }
+        x
+    }
+}
+
+fn axes(location: EastNorthElevation) -> [f64; 3] {
+    [location.easting, location.northing, location.up]
+}
+
+/// Distribute loop misclosures across the network, weighting each leg by the inverse of its length
+/// Origins keep their positions, and stations joined by shots flagged C move together.
+fn adjust<'a>(legs: &[Leg<'a>], traverse: &Traverse<'a>) -> HashMap<&'a str, EastNorthElevation> {
+    let groups = RigidGroups::new(legs, traverse);
+    let mut fixed = vec![None; groups.count];
+    for &origin in &traverse.origins {
+        fixed[groups.group[origin]] = Some(traverse.positions[origin]);
+    }
+    let mut unknown = vec![None; groups.count];
+    let mut reference_points = Vec::new();
+    for (station, &group) in &groups.group {
+        if fixed[group].is_none() && unknown[group].is_none() {
+            unknown[group] = Some(reference_points.len());
+            reference_points.push(traverse.positions[station] - groups.offset[station]);
+        }
+    }
+
+    let mut equations = NormalEquations::new(reference_points.len());
+    // Only legs between groups are adjusted, which leaves shots flagged C alone unless they join two origins
+    for leg in legs {
+        let (from_group, to_group) = (groups.group[leg.from], groups.group[leg.to]);
+        if from_group == to_group {
+            continue;
+        }
+        let weight = 1.0 / leg.length().max(MINIMUM_WEIGHTING_LENGTH);
+        // The displacement between the two groups' reference points this leg measures
+        let measured = leg.vector + groups.offset[leg.from] - groups.offset[leg.to];
+        match (unknown[from_group], unknown[to_group]) {
+            (Some(from), Some(to)) => {
+                equations.diagonal[from] += weight;
+                equations.diagonal[to] += weight;
+                equations.off_diagonal[from].push((to, -weight));
+                equations.off_diagonal[to].push((from, -weight));
+                equations.add_to_right_hand_side(from, -measured, weight);
+                equations.add_to_right_hand_side(to, measured, weight);
+            }
+            (Some(from), None) => {
+                equations.diagonal[from] += weight;
+                let to_position = fixed[to_group].unwrap();
+                equations.add_to_right_hand_side(from, to_position - measured, weight);
+            }
+            (None, Some(to)) => {
+                equations.diagonal[to] += weight;
+                let from_position = fixed[from_group].unwrap();
+                equations.add_to_right_hand_side(to, from_position + measured, weight);
+            }
+            (None, None) => (),
+        }
+    }
+
+    let solved: Vec<Vec<f64>> = (0..3)
+        .map(|axis| {
+            let start = reference_points
+                .iter()
+                .map(|point| axes(*point)[axis])
+                .collect();
+            equations.solve(axis, start)
+        })
+        .collect();
+    groups
+        .group
+        .iter()
+        .map(|(&station, &group)| {
+            let reference = match (fixed[group], unknown[group]) {
+                (Some(position), _) => position,
+                (None, Some(index)) => EastNorthElevation::from_meters(
+                    solved[0][index],
+                    solved[1][index],
+                    solved[2][index],
+                ),
+                (None, None) => unreachable!("every group is either fixed or unknown"),
+            };
+            (station, reference + groups.offset[station])
+        })
+        .collect()
+}
+
+fn adjusted_coordinates<'a>(legs: &[Leg<'a>], traverse: &Traverse<'a>) -> AdjustedCoordinates {
+    AdjustedCoordinates {
+        raw: traverse.coordinates(),
+        adjusted: to_owned_names(&adjust(legs, traverse)),
+        loops: find_loops(legs, traverse),
+    }
+}
+
+impl Survey {
+    /// Find the independent loops in the survey and how far each fails to close
+    #[must_use]
+    pub fn loop_closures(&self) -> Vec<LoopClosure> {
+        let legs = survey_legs(self, Corrections::SURVEY);
+        let traverse = Traverse::new(&legs, &[], EastNorthElevation::from_meters(0.0, 0.0, 0.0));
+        find_loops(&legs, &traverse)
+    }
+
+    /// Compute the location of every station in the survey, closing loops with a least squares adjustment
+    /// Misclosures are distributed in proportion to shot length, shots flagged C are left as measured.
+    /// See [`Survey::station_coordinates`] for how the raw coordinates are computed.
+    #[must_use]
+    pub fn adjusted_station_coordinates(&self) -> AdjustedCoordinates {
+        let legs = survey_legs(self, Corrections::SURVEY);
+        let traverse = Traverse::new(&legs, &[], EastNorthElevation::from_meters(0.0, 0.0, 0.0));
+        adjusted_coordinates(&legs, &traverse)
+    }
+}
+
+impl Project<Loaded> {
+    /// Find the independent loops in the project's shot network and how far each fails to close
+    #[must_use]
+    pub fn loop_closures(&self) -> Vec<LoopClosure> {
+        let legs = project_legs(self);
+        let traverse = Traverse::new(
+            &legs,
+            &project_anchors(self),
+            self.base_location.east_north_elevation,
+        );
+        find_loops(&legs, &traverse)
+    }
+
+    /// Compute the location of every station in the project, closing loops with a least squares adjustment
+    /// Misclosures, including those between fixed stations, are distributed in proportion to shot length.
+    /// Fixed stations keep their coordinates and shots flagged C are left as measured.
+    /// See [`Project::station_coordinates`] for how the raw coordinates are computed.
+    #[must_use]
+    pub fn adjusted_station_coordinates(&self) -> AdjustedCoordinates {
+        let legs = project_legs(self);
+        let traverse = Traverse::new(
+            &legs,
+            &project_anchors(self),
+            self.base_location.east_north_elevation,
+        );
+        adjusted_coordinates(&legs, &traverse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{test_utils::survey, Shot, ShotFlags};
+
+    const FOOT: f64 = 0.3048;
+
+    /// A square loop with the east leg taped a foot long
+    fn square(hold_east_leg: bool) -> Survey {
+        let mut east = Shot::new("B", "C", 11.0, 90.0, 0.0);
+        if hold_east_leg {
+            east.flags = Some(ShotFlags {
+                do_not_adjust: true,
+                ..ShotFlags::default()
+            });
+        }
+        survey(
+            "SQ",
+            vec![
+                Shot::new("A", "B", 10.0, 0.0, 0.0),
+                east,
+                Shot::new("C", "D", 10.0, 180.0, 0.0),
+                Shot::new("D", "A", 10.0, 270.0, 0.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn find_square_loop() {
+        let loops = square(false).loop_closures();
+        assert_eq!(loops.len(), 1);
+        let closure = &loops[0];
+        assert_eq!(closure.stations, vec!["D", "A", "B", "C"]);
+        assert_float_eq!(closure.length, 41.0 * FOOT, abs <= 1e-9);
+        assert_float_eq!(closure.misclosure.easting, FOOT, abs <= 1e-9);
+        assert_float_eq!(closure.misclosure.northing, 0.0, abs <= 1e-9);
+        assert_float_eq!(closure.misclosure_distance(), FOOT, abs <= 1e-9);
+        assert_float_eq!(closure.misclosure_ratio(), 1.0 / 41.0, abs <= 1e-9);
+    }
+
+    #[test]
+    fn adjust_square_loop() {
+        let coordinates = square(false).adjusted_station_coordinates();
+        assert_float_eq!(coordinates.raw["C"].easting, 11.0 * FOOT, abs <= 1e-9);
+        let adjusted = &coordinates.adjusted;
+        assert_eq!(
+            adjusted["A"],
+            EastNorthElevation::from_meters(0.0, 0.0, 0.0)
+        );
+        // The foot of misclosure is spread over the loop in proportion to each shot's length
+        let b = adjusted["B"];
+        assert_float_eq!(b.easting, -FOOT * 10.0 / 41.0, abs <= 1e-9);
+        assert_float_eq!(b.northing, 10.0 * FOOT, abs <= 1e-9);
+        let c = adjusted["C"];
+        assert_float_eq!(
+            c.easting - b.easting,
+            FOOT * (11.0 - 11.0 / 41.0),
+            abs <= 1e-9
+        );
+        let d = adjusted["D"];
+        assert_float_eq!(d.easting, FOOT * (10.0 + 10.0 / 41.0), abs <= 1e-9);
+    }
+
+    #[test]
+    fn hold_flagged_shots() {
+        let coordinates = square(true).adjusted_station_coordinates();
+        let adjusted = &coordinates.adjusted;
+        let (b, c) = (adjusted["B"], adjusted["C"]);
+        assert_float_eq!(c.easting - b.easting, 11.0 * FOOT, abs <= 1e-9);
+        assert_float_eq!(c.northing - b.northing, 0.0, abs <= 1e-9);
+        assert_float_eq!(b.easting, -FOOT * 10.0 / 30.0, abs <= 1e-9);
+    }
+
+    #[test]
+    fn hold_fixed_stations_joined_by_flagged_shots() {
+        let held = |from, to, length, azimuth| Shot {
+            flags: Some(ShotFlags {
+                do_not_adjust: true,
+                ..ShotFlags::default()
+            }),
+            ..Shot::new(from, to, length, azimuth, 0.0)
+        };
+        let held_chain = survey(
+            "HC",
+            vec![held("A", "B", 10.0, 90.0), held("B", "C", 10.0, 90.0)],
+        );
+        let legs = survey_legs(&held_chain, Corrections::SURVEY);
+        let a = EastNorthElevation::from_meters(100.0, 200.0, 0.0);
+        let c = EastNorthElevation::from_meters(107.0, 200.0, 0.0);
+        let traverse = Traverse::new(
+            &legs,
+            &[("A", a), ("C", c)],
+            EastNorthElevation::from_meters(0.0, 0.0, 0.0),
+        );
+        let adjusted = adjust(&legs, &traverse);
+        assert_eq!(adjusted["A"], a);
+        assert_eq!(adjusted["C"], c);
+        // B stays held to A, the shot from B to C takes up the difference between the fixed stations
+        assert_float_eq!(adjusted["B"].easting, 100.0 + 10.0 * FOOT, abs <= 1e-9);
+    }
+
+    #[test]
+    fn adjust_project() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        let coordinates = project.adjusted_station_coordinates();
+        assert_eq!(coordinates.raw, project.station_coordinates());
+        assert_eq!(coordinates.adjusted.len(), coordinates.raw.len());
+        for (name, location) in project.fixed_station_locations() {
+            assert_eq!(coordinates.adjusted[name], location);
+        }
+        assert_eq!(coordinates.loops, project.loop_closures());
+        assert!(!coordinates.loops.is_empty());
+        for closure in &coordinates.loops {
+            assert!(closure.misclosure_ratio() < 0.05, "{closure:?}");
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{project::Loaded, Project, Shot, Survey};
+
+use super::{azimuth_difference, backsight, frontsight};
+
+/// Shots within this many degrees of vertical have no meaningful azimuth to compare
+const VERTICAL_TOLERANCE: f64 = 0.01;
+
+/// How far a shot's frontsight and backsight may disagree, in degrees, before the shot is reported
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BacksightTolerances {
+    pub azimuth: f64,
+    pub inclination: f64,
+}
+
+impl Default for BacksightTolerances {
+    /// Two degrees for both readings, the usual standard for cave survey instruments
+    fn default() -> Self {
+        Self {
+            azimuth: 2.0,
+            inclination: 2.0,
+        }
+    }
+}
+
+/// How far a shot's corrected frontsight and corrected backsight disagree
+///
+/// The backsight is turned around before comparing, so the differences are zero for perfectly agreeing readings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BacksightDiscrepancy<'a> {
+    pub survey: &'a Survey,
+    pub shot: &'a Shot,
+    /// Backsight minus frontsight azimuth in degrees, `None` if either wasn't read or the shot is vertical
+    pub azimuth: Option<f64>,
+    /// Backsight minus frontsight inclination in degrees, `None` if either wasn't read
+    pub inclination: Option<f64>,
+    /// The azimuths disagree by more than the tolerance
+    pub azimuth_out_of_tolerance: bool,
+    /// The inclinations disagree by more than the tolerance
+    pub inclination_out_of_tolerance: bool,
+}
+
+impl BacksightDiscrepancy<'_> {
+    /// Whether either reading disagrees by more than the tolerance, suggesting a blunder
+    #[must_use]
+    pub fn is_out_of_tolerance(&self) -> bool {
+        self.azimuth_out_of_tolerance || self.inclination_out_of_tolerance
+    }
+}
+
+fn discrepancy<'a>(
+    survey: &'a Survey,
+    shot: &'a Shot,
+    tolerances: BacksightTolerances,
+) -> BacksightDiscrepancy<'a> {
+    let (front_azimuth, front_inclination) = frontsight(shot, &survey.parameters, true);
+    let (back_azimuth, back_inclination) = backsight(shot, &survey.parameters, true);
+    let vertical = [front_inclination, back_inclination]
+        .into_iter()
+        .flatten()
+        .any(|inclination| (inclination.abs() - 90.0).abs() < VERTICAL_TOLERANCE);
+    let azimuth = match (front_azimuth, back_azimuth) {
+        (Some(front), Some(back)) if !vertical => Some(azimuth_difference(front, back)),
+        _ => None,
+    };
+    let inclination = front_inclination
+        .zip(back_inclination)
+        .map(|(front, back)| back - front);
+    BacksightDiscrepancy {
+        survey,
+        shot,
+        azimuth,
+        inclination,
+        azimuth_out_of_tolerance: azimuth.is_some_and(|azimuth| azimuth.abs() > tolerances.azimuth),
+        inclination_out_of_tolerance: inclination
+            .is_some_and(|inclination| inclination.abs() > tolerances.inclination),
+    }
+}
+
+impl Survey {
+    /// Compare the corrected frontsight and backsight of every shot in the survey
+    /// Surveys without redundant backsights in their FORMAT have nothing to compare, and produce an empty report.
+    /// # Returns
+    /// A discrepancy for each shot, in survey order
+    #[must_use]
+    pub fn backsight_discrepancies(
+        &self,
+        tolerances: BacksightTolerances,
+    ) -> Vec<BacksightDiscrepancy<'_>> {
+        if !self.parameters.has_redundant_backsights() {
+            return Vec::new();
+        }
+        self.shots
+            .iter()
+            .map(|shot| discrepancy(self, shot, tolerances))
+            .collect()
+    }
+}
+
+impl Project<Loaded> {
+    /// Find the shots in the project whose frontsight and backsight disagree by more than the tolerances
+    /// # Returns
+    /// The discrepancies which are out of tolerance, in project order
+    #[must_use]
+    pub fn backsight_blunders(
+        &self,
+        tolerances: BacksightTolerances,
+    ) -> Vec<BacksightDiscrepancy<'_>> {
+        self.surveys()
+            .flat_map(|(_, survey)| survey.backsight_discrepancies(tolerances))
+            .filter(BacksightDiscrepancy::is_out_of_tolerance)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{
+        BackSightCorrectionFactors, CorrectionFactors, Datum, EastNorthElevation, Measurement,
+        SurveyFile, UtmLocation,
+    };
+
+    const SURVEY: &str = "Test Cave
+SURVEY NAME: BS
+SURVEY DATE: 7 10 2021  COMMENT:Backsight checks
+SURVEY TEAM:
+Someone,Someone Else
+DECLINATION: 0.00  FORMAT: DDDDLUDRLADBF  CORRECTIONS: 0.00 0.00 0.00 CORRECTIONS2: 0.0 0.0
+
+        FROM           TO   LENGTH  BEARING      INC     LEFT       UP     DOWN    RIGHT     AZM2     INC2
+
+          B1           B2    10.00   359.50    10.00     1.00     1.00     1.00     1.00   180.00   -11.00
+          B2           B3    10.00    45.00    -5.00     1.00     1.00     1.00     1.00   230.00     5.00
+          B3           B4    10.00    90.00     0.00     1.00     1.00     1.00     1.00  -999.00  -999.00
+          B4           B5    10.00     0.00    90.00     1.00     1.00     1.00     1.00   123.00   -90.00
+\x0c
+";
+
+    #[test]
+    fn compare_backsights() {
+        let survey = Survey::parse_survey(SURVEY).unwrap();
+        let report = survey.backsight_discrepancies(BacksightTolerances::default());
+        assert_eq!(report.len(), 4);
+
+        assert_float_eq!(report[0].azimuth.unwrap(), 0.5, abs <= 1e-9);
+        assert_float_eq!(report[0].inclination.unwrap(), 1.0, abs <= 1e-9);
+        assert!(!report[0].is_out_of_tolerance());
+
+        assert_float_eq!(report[1].azimuth.unwrap(), 5.0, abs <= 1e-9);
+        assert!(report[1].azimuth_out_of_tolerance);
+        assert!(!report[1].inclination_out_of_tolerance);
+        assert!(report[1].is_out_of_tolerance());
+
+        assert_eq!(report[2].azimuth, None);
+        assert_eq!(report[2].inclination, None);
+        assert!(!report[2].is_out_of_tolerance());
+
+        // Vertical shots have no azimuth to compare
+        assert_eq!(report[3].azimuth, None);
+        assert!(!report[3].is_out_of_tolerance());
+
+        let loose = BacksightTolerances {
+            azimuth: 5.5,
+            inclination: 2.0,
+        };
+        assert!(survey
+            .backsight_discrepancies(loose)
+            .iter()
+            .all(|discrepancy| !discrepancy.is_out_of_tolerance()));
+    }
+
+    #[test]
+    fn correction_factors() {
+        let mut survey = Survey::parse_survey(SURVEY).unwrap();
+        survey.parameters.correction_factors = Some(CorrectionFactors {
+            azimuth: 2.5,
+            inclination: 0.0,
+            length: 0.0,
+        });
+        survey.parameters.backsight_correction_factors = Some(BackSightCorrectionFactors {
+            azimuth: -2.5,
+            inclination: 1.0,
+        });
+        let report = survey.backsight_discrepancies(BacksightTolerances::default());
+        assert_float_eq!(report[0].azimuth.unwrap(), -4.5, abs <= 1e-9);
+        assert_float_eq!(report[0].inclination.unwrap(), 0.0, abs <= 1e-9);
+        assert_float_eq!(report[1].azimuth.unwrap(), 0.0, abs <= 1e-9);
+        assert!(report[0].is_out_of_tolerance());
+        assert!(!report[1].is_out_of_tolerance());
+    }
+
+    #[test]
+    fn surveys_without_backsights() {
+        let mut survey = Survey::parse_survey(SURVEY).unwrap();
+        survey.parameters.format = None;
+        survey.shots[1].backsight_azimuth = Measurement::Measured(100.0);
+        assert!(survey
+            .backsight_discrepancies(BacksightTolerances::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn project_blunders() {
+        let base_location = UtmLocation {
+            east_north_elevation: EastNorthElevation::from_meters(500_000.0, 4_000_000.0, 0.0),
+            zone: 13,
+            hemisphere: crate::Hemisphere::North,
+            convergence_angle: 0.0,
+        };
+        let mut project = Project::new("blunders.mak", base_location, Datum::Wgs1984, None);
+        let survey_file = project.add_survey_file(SurveyFile::new("blunders.dat"));
+        survey_file.add_survey(Survey::parse_survey(SURVEY).unwrap());
+        let blunders = project.backsight_blunders(BacksightTolerances::default());
+        assert_eq!(blunders.len(), 1);
+        assert_eq!(blunders[0].shot.from, "B2");
+        assert_eq!(blunders[0].survey.name, "BS");
+    }
+}
@@ -0,0 +1,597 @@
+//! Centerline reduction
+//!
+//! Turns the shots in survey data into coordinates for each station,
+//! by walking the network of shots out from stations with known locations.
+//! Survey data stores lengths in feet, computed coordinates are always in meters.
+
+mod adjustment;
+mod backsights;
+
+pub use adjustment::{AdjustedCoordinates, LoopClosure};
+pub use backsights::{BacksightDiscrepancy, BacksightTolerances};
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    common_types::FEET_TO_METERS,
+    project::{Loaded, ProjectParameters},
+    DeclinationMode, EastNorthElevation, Parameters, Project, Shot, Survey, SurveyFile,
+};
+
+/// Which adjustments are made to the raw instrument readings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Corrections {
+    /// Add the survey's declination to azimuths
+    pub(crate) declination: bool,
+    /// Apply the survey's correction factors
+    pub(crate) instrument: bool,
+    /// Grid convergence in degrees, subtracted from azimuths when set
+    pub(crate) convergence: Option<f64>,
+    /// Leave out shots flagged X
+    pub(crate) shot_flags: bool,
+}
+
+impl Corrections {
+    /// Everything a survey can correct on its own, without a project to supply convergence
+    pub(crate) const SURVEY: Self = Self {
+        declination: true,
+        instrument: true,
+        convergence: None,
+        shot_flags: true,
+    };
+
+    /// The corrections a project's settings call for when processing one of its files
+    /// File parameters override the project's, and options which are never set fall back to Compass' defaults
+    pub(crate) fn for_file(project: &Project<Loaded>, survey_file: &SurveyFile<Loaded>) -> Self {
+        let parameters = survey_file
+            .parameters
+            .as_ref()
+            .or(project.parameters.as_ref());
+        let option = |get: fn(&ProjectParameters) -> Option<bool>| parameters.and_then(get);
+        let apply_convergence = option(|p| p.apply_convergence)
+            .or(project.utm_convergence)
+            .unwrap_or(false);
+        Self {
+            declination: parameters.and_then(|p| p.declination_mode)
+                != Some(DeclinationMode::Ignore),
+            instrument: option(|p| p.apply_corrections).unwrap_or(true),
+            convergence: apply_convergence.then_some(project.base_location.convergence_angle),
+            shot_flags: option(|p| p.apply_shot_flags).unwrap_or(true),
+        }
+    }
+
+    pub(crate) fn excludes(self, shot: &Shot) -> bool {
+        self.shot_flags
+            && shot
+                .flags
+                .as_ref()
+                .is_some_and(|flags| flags.exclude_from_processing)
+    }
+
+    fn holds(self, shot: &Shot) -> bool {
+        self.shot_flags && shot.flags.as_ref().is_some_and(|flags| flags.do_not_adjust)
+    }
+}
+
+/// A shot reduced to the displacement between its stations
+#[derive(Clone, Debug)]
+pub(crate) struct Leg<'a> {
+    pub(crate) from: &'a str,
+    pub(crate) to: &'a str,
+    /// Displacement from `from` to `to` in meters
+    pub(crate) vector: EastNorthElevation,
+    /// Shots flagged C keep their measured displacement when loops are closed
+    pub(crate) do_not_adjust: bool,
+}
+
+impl Leg<'_> {
+    /// The corrected tape length in meters
+    pub(crate) fn length(&self) -> f64 {
+        let EastNorthElevation {
+            easting,
+            northing,
+            up,
+        } = self.vector;
+        (easting * easting + northing * northing + up * up).sqrt()
+    }
+}
+
+/// Wrap an angle in degrees into `[0, 360)`
+pub(crate) fn normalize_azimuth(azimuth: f64) -> f64 {
+    azimuth.rem_euclid(360.0)
+}
+
+/// The signed difference `to - from` between two azimuths in degrees, in `[-180, 180)`
+pub(crate) fn azimuth_difference(from: f64, to: f64) -> f64 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// The frontsight azimuth and inclination in degrees, with correction factors applied if requested
+pub(crate) fn frontsight(
+    shot: &Shot,
+    parameters: &Parameters,
+    instrument: bool,
+) -> (Option<f64>, Option<f64>) {
+    let (azimuth_correction, inclination_correction) =
+        match (&parameters.correction_factors, instrument) {
+            (Some(factors), true) => (factors.azimuth, factors.inclination),
+            _ => (0.0, 0.0),
+        };
+    (
+        shot.azimuth
+            .value()
+            .map(|azimuth| normalize_azimuth(azimuth + azimuth_correction)),
+        shot.inclination
+            .value()
+            .map(|inclination| inclination + inclination_correction),
+    )
+}
+
+/// The backsight azimuth and inclination in degrees, corrected if requested
+/// and turned around to point the same way as the frontsight
+pub(crate) fn backsight(
+    shot: &Shot,
+    parameters: &Parameters,
+    instrument: bool,
+) -> (Option<f64>, Option<f64>) {
+    if !parameters.has_redundant_backsights() {
+        return (None, None);
+    }
+    let (azimuth_correction, inclination_correction) =
+        match (&parameters.backsight_correction_factors, instrument) {
+            (Some(factors), true) => (factors.azimuth, factors.inclination),
+            _ => (0.0, 0.0),
+        };
+    (
+        shot.backsight_azimuth
+            .value()
+            .map(|azimuth| normalize_azimuth(azimuth + azimuth_correction - 180.0)),
+        shot.backsight_inclination
+            .value()
+            .map(|inclination| -(inclination + inclination_correction)),
+    )
+}
+
+/// The azimuth and inclination used to reduce a shot, averaging the frontsight and backsight when both were read
+/// A vertical shot doesn't need an azimuth, and is taken to point north without one.
+/// # Returns
+/// `None` if the shot has no inclination, or has no azimuth and isn't vertical
+fn shot_angles(shot: &Shot, parameters: &Parameters, instrument: bool) -> Option<(f64, f64)> {
+    let (front_azimuth, front_inclination) = frontsight(shot, parameters, instrument);
+    let (back_azimuth, back_inclination) = backsight(shot, parameters, instrument);
+    let inclination = match (front_inclination, back_inclination) {
+        (Some(front), Some(back)) => f64::midpoint(front, back),
+        (front, back) => front.or(back)?,
+    };
+    let azimuth = match (front_azimuth, back_azimuth) {
+        (Some(front), Some(back)) => {
+            normalize_azimuth(front + azimuth_difference(front, back) / 2.0)
+        }
+        (None, None) if (inclination.abs() - 90.0).abs() < VERTICAL_TOLERANCE => 0.0,
+        (front, back) => front.or(back)?,
+    };
+    Some((azimuth, inclination))
+}
+
+/// How close to plumb in degrees a shot must be to count as vertical
+const VERTICAL_TOLERANCE: f64 = 1e-9;
+
+/// Reduce a shot to the displacement from its from station to its to station
+/// # Returns
+/// `None` if the shot's angles can't place its to station, see [`shot_angles`]
+fn leg<'a>(shot: &'a Shot, parameters: &Parameters, corrections: Corrections) -> Option<Leg<'a>> {
+    let (mut azimuth, inclination) = shot_angles(shot, parameters, corrections.instrument)?;
+    if corrections.declination {
+        azimuth += parameters.declination;
+    }
+    if let Some(convergence) = corrections.convergence {
+        azimuth -= convergence;
+    }
+    let length_correction = match (&parameters.correction_factors, corrections.instrument) {
+        (Some(factors), true) => factors.length,
+        _ => 0.0,
+    };
+    let length = (shot.length + length_correction) * FEET_TO_METERS;
+    let (azimuth, inclination) = (azimuth.to_radians(), inclination.to_radians());
+    let horizontal = length * inclination.cos();
+    Some(Leg {
+        from: &shot.from,
+        to: &shot.to,
+        vector: EastNorthElevation::from_meters(
+            horizontal * azimuth.sin(),
+            horizontal * azimuth.cos(),
+            length * inclination.sin(),
+        ),
+        do_not_adjust: corrections.holds(shot),
+    })
+}
+
+/// The legs of every shot in a survey which takes part in processing
+/// Shots without the readings to place their to station are left out, [`unusable_shots`] lists them.
+pub(crate) fn survey_legs(survey: &Survey, corrections: Corrections) -> Vec<Leg<'_>> {
+    survey
+        .shots
+        .iter()
+        .filter(|shot| !corrections.excludes(shot))
+        .filter_map(|shot| leg(shot, &survey.parameters, corrections))
+        .collect()
+}
+
+/// The shots in a survey which take part in processing but can't be reduced
+fn unusable_shots(survey: &Survey, corrections: Corrections) -> impl Iterator<Item = &Shot> {
+    survey.shots.iter().filter(move |shot| {
+        !corrections.excludes(shot)
+            && shot_angles(shot, &survey.parameters, corrections.instrument).is_none()
+    })
+}
+
+/// The legs of every shot in a project which takes part in processing, in project order
+pub(crate) fn project_legs(project: &Project<Loaded>) -> Vec<Leg<'_>> {
+    let mut legs = Vec::new();
+    for survey_file in &project.survey_files {
+        let corrections = Corrections::for_file(project, survey_file);
+        for survey in survey_file.surveys() {
+            legs.extend(survey_legs(survey, corrections));
+        }
+    }
+    legs
+}
+
+/// A project's fixed stations with their locations on the base location's grid, in project order
+pub(crate) fn project_anchors(project: &Project<Loaded>) -> Vec<(&str, EastNorthElevation)> {
+    let locations = project.fixed_station_locations();
+    project
+        .survey_files
+        .iter()
+        .flat_map(SurveyFile::fixed_stations)
+        .map(|station| (station.name(), locations[station.name()]))
+        .collect()
+}
+
+/// The result of walking out along the legs from the anchored stations
+#[derive(Debug)]
+pub(crate) struct Traverse<'a> {
+    pub(crate) positions: HashMap<&'a str, EastNorthElevation>,
+    /// The leg each station was reached by, stations positioned without a leg aren't included
+    pub(crate) reached_by: HashMap<&'a str, usize>,
+    /// Stations positioned without a leg: the anchors, then the first station of each unanchored group
+    pub(crate) origins: Vec<&'a str>,
+}
+
+impl<'a> Traverse<'a> {
+    /// Position every station by walking out along the legs from the anchored stations
+    /// Each station takes its position from the first leg to reach it, legs are followed in either direction.
+    /// Groups of stations which aren't connected to any anchor are started from their first station,
+    /// placed at `unanchored_origin`.
+    pub(crate) fn new(
+        legs: &[Leg<'a>],
+        anchors: &[(&'a str, EastNorthElevation)],
+        unanchored_origin: EastNorthElevation,
+    ) -> Self {
+        let mut neighbours: HashMap<&str, Vec<(usize, &str, EastNorthElevation)>> = HashMap::new();
+        let mut stations = Vec::new();
+        for (index, leg) in legs.iter().enumerate() {
+            for (station, neighbour, vector) in [
+                (leg.from, leg.to, leg.vector),
+                (leg.to, leg.from, -leg.vector),
+            ] {
+                neighbours
+                    .entry(station)
+                    .or_insert_with(|| {
+                        stations.push(station);
+                        Vec::new()
+                    })
+                    .push((index, neighbour, vector));
+            }
+        }
+
+        let mut traverse = Self {
+            positions: HashMap::new(),
+            reached_by: HashMap::new(),
+            origins: Vec::new(),
+        };
+        let mut queue = VecDeque::new();
+        for &(station, location) in anchors {
+            if !traverse.positions.contains_key(station) {
+                traverse.positions.insert(station, location);
+                traverse.origins.push(station);
+                queue.push_back(station);
+            }
+        }
+        let mut unvisited = stations.iter();
+        loop {
+            while let Some(station) = queue.pop_front() {
+                let position = traverse.positions[station];
+                for &(index, neighbour, vector) in neighbours.get(station).into_iter().flatten() {
+                    if !traverse.positions.contains_key(neighbour) {
+                        traverse.positions.insert(neighbour, position + vector);
+                        traverse.reached_by.insert(neighbour, index);
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+            let Some(&start) = unvisited.find(|station| !traverse.positions.contains_key(*station))
+            else {
+                break;
+            };
+            traverse.positions.insert(start, unanchored_origin);
+            traverse.origins.push(start);
+            queue.push_back(start);
+        }
+        traverse
+    }
+
+    /// The positions keyed by owned station names
+    pub(crate) fn coordinates(&self) -> HashMap<String, EastNorthElevation> {
+        to_owned_names(&self.positions)
+    }
+}
+
+pub(crate) fn to_owned_names(
+    positions: &HashMap<&str, EastNorthElevation>,
+) -> HashMap<String, EastNorthElevation> {
+    positions
+        .iter()
+        .map(|(station, position)| ((*station).to_string(), *position))
+        .collect()
+}
+
+impl Survey {
+    /// Compute the location of every station in the survey
+    /// The first station is placed at the origin, and the survey's declination and correction factors are applied.
+    /// Shots flagged X are left out, along with any stations only they reach.
+    /// Shots listed by [`Survey::unusable_shots`] are left out the same way.
+    /// # Returns
+    /// Map from station name to its location in meters relative to the first station
+    #[must_use]
+    pub fn station_coordinates(&self) -> HashMap<String, EastNorthElevation> {
+        let legs = survey_legs(self, Corrections::SURVEY);
+        Traverse::new(&legs, &[], EastNorthElevation::from_meters(0.0, 0.0, 0.0)).coordinates()
+    }
+
+    /// Find the shots which can't be used to compute station locations
+    /// A shot needs an inclination from its frontsight or backsight, and an azimuth too unless it is vertical.
+    /// Shots flagged X aren't listed, they are left out of processing anyway.
+    /// # Returns
+    /// The shots without enough readings, in survey order
+    #[must_use]
+    pub fn unusable_shots(&self) -> Vec<&Shot> {
+        unusable_shots(self, Corrections::SURVEY).collect()
+    }
+}
+
+impl Project<Loaded> {
+    /// Compute the location of every station in the project
+    /// Stations are positioned from the project's fixed stations, applying declination, correction factors,
+    /// UTM convergence and shot flags as the project and file parameters call for.
+    /// Declinations are taken from the survey files, even when the project asks for automatic declinations.
+    /// Parts of the cave without a fixed station start from the base location.
+    /// Loops are not adjusted, each station takes its position from the first shot to reach it.
+    /// Shots listed by [`Project::unusable_shots`] are left out.
+    /// # Returns
+    /// Map from station name to its location in meters, on the base location's UTM grid
+    #[must_use]
+    pub fn station_coordinates(&self) -> HashMap<String, EastNorthElevation> {
+        let legs = project_legs(self);
+        Traverse::new(
+            &legs,
+            &project_anchors(self),
+            self.base_location.east_north_elevation,
+        )
+        .coordinates()
+    }
+
+    /// Find the shots which can't be used to compute station locations
+    /// A shot needs an inclination from its frontsight or backsight, and an azimuth too unless it is vertical.
+    /// Shots left out of processing by their X flag aren't listed.
+    /// # Returns
+    /// The shots without enough readings, in project order
+    #[must_use]
+    pub fn unusable_shots(&self) -> Vec<&Shot> {
+        let mut shots = Vec::new();
+        for survey_file in &self.survey_files {
+            let corrections = Corrections::for_file(self, survey_file);
+            for survey in survey_file.surveys() {
+                shots.extend(unusable_shots(survey, corrections));
+            }
+        }
+        shots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{
+        test_utils::survey, BackSightCorrectionFactors, BacksightMode, BearingUnits,
+        CorrectionFactors, InclinationUnits, LengthUnits, LrudItem, Measurement, ShotFlags,
+        ShotItem, SurveyFormat,
+    };
+
+    fn assert_location(location: EastNorthElevation, easting: f64, northing: f64, up: f64) {
+        assert_float_eq!(location.easting, easting, abs <= 1e-9);
+        assert_float_eq!(location.northing, northing, abs <= 1e-9);
+        assert_float_eq!(location.up, up, abs <= 1e-9);
+    }
+
+    #[test]
+    fn traverse_survey() {
+        let survey = survey(
+            "T",
+            vec![
+                Shot::new("A1", "A2", 10.0, 90.0, 0.0),
+                Shot::new("A2", "A3", 10.0, 0.0, 90.0),
+                // Entered from the far end, so A4 has to be positioned from A3 backwards
+                Shot::new("A4", "A3", 10.0, 180.0, 30.0),
+            ],
+        );
+        let coordinates = survey.station_coordinates();
+        assert_eq!(coordinates.len(), 4);
+        assert_location(coordinates["A1"], 0.0, 0.0, 0.0);
+        assert_location(coordinates["A2"], 3.048, 0.0, 0.0);
+        assert_location(coordinates["A3"], 3.048, 0.0, 3.048);
+        assert_location(
+            coordinates["A4"],
+            3.048,
+            3.048 * 30f64.to_radians().cos(),
+            3.048 - 1.524,
+        );
+    }
+
+    #[test]
+    fn declination_and_corrections() {
+        let mut survey = survey("T", vec![Shot::new("A1", "A2", 9.0, 75.0, -3.0)]);
+        survey.parameters.declination = 10.0;
+        survey.parameters.correction_factors = Some(CorrectionFactors {
+            azimuth: 5.0,
+            inclination: 3.0,
+            length: 1.0,
+        });
+        let coordinates = survey.station_coordinates();
+        assert_location(coordinates["A2"], 3.048, 0.0, 0.0);
+    }
+
+    #[test]
+    fn excluded_shots() {
+        let mut excluded = Shot::new("A2", "A3", 10.0, 0.0, 0.0);
+        excluded.flags = Some(ShotFlags {
+            exclude_from_processing: true,
+            ..ShotFlags::default()
+        });
+        let survey = survey("T", vec![Shot::new("A1", "A2", 10.0, 0.0, 0.0), excluded]);
+        let coordinates = survey.station_coordinates();
+        assert_eq!(coordinates.len(), 2);
+        assert!(!coordinates.contains_key("A3"));
+    }
+
+    #[test]
+    fn averaged_backsights() {
+        let mut survey = survey("T", Vec::new());
+        survey.parameters.format = Some(SurveyFormat {
+            bearing_units: BearingUnits::Degrees,
+            length_units: LengthUnits::DecimalFeet,
+            passage_units: LengthUnits::DecimalFeet,
+            inclination_units: InclinationUnits::Degrees,
+            passage_dimension_order: [
+                LrudItem::Up,
+                LrudItem::Down,
+                LrudItem::Left,
+                LrudItem::Right,
+            ],
+            shot_item_order: vec![ShotItem::Length, ShotItem::Azimuth, ShotItem::Inclination],
+            backsight_mode: Some(BacksightMode::Redundant),
+            lrud_association: None,
+        });
+        survey.parameters.backsight_correction_factors = Some(BackSightCorrectionFactors {
+            azimuth: 1.0,
+            inclination: 0.0,
+        });
+        let mut shot = Shot::new("A1", "A2", 10.0, 359.0, 2.0);
+        shot.backsight_azimuth = Measurement::Measured(180.0);
+        shot.backsight_inclination = Measurement::Measured(-2.0);
+        let mut backsight_only = Shot::new("A2", "A3", 10.0, 0.0, 0.0);
+        backsight_only.azimuth = Measurement::MISSING_ANGLE;
+        backsight_only.inclination = Measurement::MISSING_ANGLE;
+        backsight_only.backsight_azimuth = Measurement::Measured(269.0);
+        backsight_only.backsight_inclination = Measurement::Measured(0.0);
+        survey.shots = vec![shot, backsight_only];
+
+        let coordinates = survey.station_coordinates();
+        let a2 = coordinates["A2"];
+        assert_float_eq!(a2.easting, 0.0, abs <= 1e-9);
+        assert_float_eq!(a2.up, 3.048 * 2f64.to_radians().sin(), abs <= 1e-9);
+        assert_location(coordinates["A3"] - a2, 3.048, 0.0, 0.0);
+    }
+
+    #[test]
+    fn shots_missing_readings() {
+        let mut vertical = Shot::new("A1", "A2", 10.0, 0.0, 90.0);
+        vertical.azimuth = Measurement::MISSING_ANGLE;
+        let mut no_azimuth = Shot::new("A2", "A3", 10.0, 0.0, 10.0);
+        no_azimuth.azimuth = Measurement::MISSING_ANGLE;
+        let mut no_inclination = Shot::new("A2", "A4", 10.0, 90.0, 0.0);
+        no_inclination.inclination = Measurement::MISSING_ANGLE;
+        let survey = survey("T", vec![vertical, no_azimuth, no_inclination]);
+
+        let unusable = survey.unusable_shots();
+        assert_eq!(unusable.len(), 2);
+        assert_eq!(unusable[0].to, "A3");
+        assert_eq!(unusable[1].to, "A4");
+        let coordinates = survey.station_coordinates();
+        assert_eq!(coordinates.len(), 2);
+        assert_location(coordinates["A2"], 0.0, 0.0, 3.048);
+    }
+
+    #[test]
+    fn project_coordinates() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let mut project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        // Hold the survey at a single station to check the shots agree with the other fixes
+        for station in ["SC3", "S4", "SS6"] {
+            project.survey_files[0].remove_station(station);
+        }
+        let coordinates = project.station_coordinates();
+        let fixes = Project::read(&sample_path)
+            .unwrap()
+            .fixed_station_locations()
+            .into_iter()
+            .map(|(name, location)| (name.to_string(), location))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(coordinates["A1"], fixes["A1"]);
+        for station in ["SC3", "S4", "SS6"] {
+            let error = coordinates[station] - fixes[station];
+            assert!(
+                error.easting.hypot(error.northing) < 0.5,
+                "{station} is off by {error:?}"
+            );
+        }
+        // SS6's fixed elevation disagrees with the surface survey leading to it, so only check the cave fixes
+        for station in ["SC3", "S4"] {
+            let error = coordinates[station] - fixes[station];
+            assert!(error.up.abs() < 0.5, "{station} is off by {error:?}");
+        }
+        let stations: std::collections::HashSet<_> = project
+            .shots()
+            .flat_map(|found| [found.shot.from.as_str(), found.shot.to.as_str()])
+            .collect();
+        assert_eq!(coordinates.len(), stations.len());
+    }
+
+    #[test]
+    fn project_convergence() {
+        let base_location = crate::UtmLocation {
+            east_north_elevation: EastNorthElevation::from_meters(500_000.0, 4_000_000.0, 0.0),
+            zone: 13,
+            hemisphere: crate::Hemisphere::North,
+            convergence_angle: 0.0,
+        };
+        let mut project = Project::new(
+            "convergence.mak",
+            base_location,
+            crate::Datum::Wgs1984,
+            None,
+        );
+        // On the central meridian the computed convergence is zero, so exaggerate it
+        project.base_location.convergence_angle = 1.5;
+        let survey_file = project.add_survey_file(SurveyFile::new("convergence.dat"));
+        survey_file.add_survey(survey("T", vec![Shot::new("A1", "A2", 100.0, 1.5, 0.0)]));
+
+        let north = project.station_coordinates()["A2"] - base_location.east_north_elevation;
+        assert_float_eq!(
+            north.easting,
+            30.48 * 1.5f64.to_radians().sin(),
+            abs <= 1e-9
+        );
+        project.utm_convergence = Some(true);
+        let grid = project.station_coordinates()["A2"] - base_location.east_north_elevation;
+        assert_location(grid, 0.0, 30.48, 0.0);
+    }
+}
@@ -0,0 +1,378 @@
+//! Survey statistics
+//!
+//! Totals and extents for a survey, a survey file or a whole project,
+//! as reported in annual survey reports.
+//! Lengths and coordinates are in meters.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    common_types::FEET_TO_METERS,
+    project::Loaded,
+    reduction::{survey_legs, Corrections, Traverse},
+    Date, EastNorthElevation, Error, Project, Shot, Survey, SurveyFile,
+};
+
+/// Statistics for a survey, survey file or project
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    /// Total length of the shots in meters, leaving out shots flagged L or X
+    pub length: f64,
+    /// Number of shots in the survey data, including flagged shots
+    pub shot_count: usize,
+    /// Number of distinct stations positioned by the survey data
+    pub station_count: usize,
+    /// Lowest south west and highest north east corners of the box containing every station,
+    /// `None` when there are no stations
+    pub bounds: Option<(EastNorthElevation, EastNorthElevation)>,
+    /// Height of the highest station above the reference station, in meters
+    pub height_above_reference: Option<f64>,
+    /// Depth of the lowest station below the reference station, in meters
+    pub depth_below_reference: Option<f64>,
+    /// Date of the earliest survey, with two digit years read as 19xx
+    pub first_date: Option<Date>,
+    /// Date of the latest survey, with two digit years read as 19xx
+    pub last_date: Option<Date>,
+    /// Length surveyed by each team member, in meters
+    /// Every member of a survey's team is credited with the survey's full length.
+    pub length_by_surveyor: BTreeMap<String, f64>,
+    /// Length surveyed in each year, in meters, with two digit years read as 19xx
+    pub length_by_year: BTreeMap<u16, f64>,
+}
+
+impl Statistics {
+    /// East-west and north-south size of the box containing every station, in meters
+    #[must_use]
+    pub fn horizontal_extent(&self) -> (f64, f64) {
+        self.bounds.map_or((0.0, 0.0), |(min, max)| {
+            (max.easting - min.easting, max.northing - min.northing)
+        })
+    }
+
+    /// Difference in elevation between the highest and lowest stations, in meters
+    #[must_use]
+    pub fn vertical_range(&self) -> f64 {
+        self.bounds.map_or(0.0, |(min, max)| max.up - min.up)
+    }
+}
+
+/// Whether a shot counts toward surveyed length
+fn counts_toward_length(shot: &Shot) -> bool {
+    !shot
+        .flags
+        .as_ref()
+        .is_some_and(|flags| flags.exclude_from_length || flags.exclude_from_processing)
+}
+
+/// The names in a survey's team line, which Compass leaves as free text
+/// Names are separated by commas or semicolons, and blank entries are skipped.
+fn team_members(team: &str) -> impl Iterator<Item = &str> {
+    team.split([',', ';'])
+        .map(str::trim)
+        .filter(|member| !member.is_empty())
+}
+
+fn collect<'a>(
+    surveys: impl IntoIterator<Item = &'a Survey>,
+    coordinates: &HashMap<String, EastNorthElevation>,
+    reference_station: Option<&str>,
+) -> Result<Statistics, Error> {
+    let mut statistics = Statistics {
+        length: 0.0,
+        shot_count: 0,
+        station_count: coordinates.len(),
+        bounds: None,
+        height_above_reference: None,
+        depth_below_reference: None,
+        first_date: None,
+        last_date: None,
+        length_by_surveyor: BTreeMap::new(),
+        length_by_year: BTreeMap::new(),
+    };
+    for survey in surveys {
+        let length: f64 = survey
+            .shots
+            .iter()
+            .filter(|shot| counts_toward_length(shot))
+            .map(|shot| shot.length * FEET_TO_METERS)
+            .sum();
+        statistics.length += length;
+        statistics.shot_count += survey.shots.len();
+        let survey_date = Date {
+            year: survey.date.full_year(),
+            ..survey.date
+        };
+        statistics.first_date = Some(
+            statistics
+                .first_date
+                .map_or(survey_date, |date| date.min(survey_date)),
+        );
+        statistics.last_date = Some(
+            statistics
+                .last_date
+                .map_or(survey_date, |date| date.max(survey_date)),
+        );
+        for member in team_members(&survey.team) {
+            *statistics
+                .length_by_surveyor
+                .entry(member.to_string())
+                .or_default() += length;
+        }
+        *statistics
+            .length_by_year
+            .entry(survey_date.year)
+            .or_default() += length;
+    }
+
+    for &location in coordinates.values() {
+        statistics.bounds = Some(match statistics.bounds {
+            None => (location, location),
+            Some((min, max)) => (
+                EastNorthElevation::from_meters(
+                    min.easting.min(location.easting),
+                    min.northing.min(location.northing),
+                    min.up.min(location.up),
+                ),
+                EastNorthElevation::from_meters(
+                    max.easting.max(location.easting),
+                    max.northing.max(location.northing),
+                    max.up.max(location.up),
+                ),
+            ),
+        });
+    }
+    if let Some(station) = reference_station {
+        let reference = coordinates
+            .get(station)
+            .ok_or_else(|| Error::StationNotFound(station.to_string()))?;
+        if let Some((min, max)) = statistics.bounds {
+            statistics.height_above_reference = Some(max.up - reference.up);
+            statistics.depth_below_reference = Some(reference.up - min.up);
+        }
+    }
+    Ok(statistics)
+}
+
+impl Survey {
+    /// Compute statistics for the survey
+    /// Extents are measured from [`Survey::station_coordinates`].
+    /// # Arguments
+    /// - `reference_station` - Station to measure the height and depth of the survey from
+    /// # Errors
+    /// - [`Error::StationNotFound`] If the reference station isn't in the survey
+    pub fn statistics(&self, reference_station: Option<&str>) -> Result<Statistics, Error> {
+        collect([self], &self.station_coordinates(), reference_station)
+    }
+}
+
+impl SurveyFile<Loaded> {
+    /// Compute statistics for the surveys in the file
+    /// The file is processed on its own, held at its fixed stations as written in the project file,
+    /// or with its first station at the origin if it has none.
+    /// # Arguments
+    /// - `reference_station` - Station to measure the file's height and depth from
+    /// # Errors
+    /// - [`Error::StationNotFound`] If the reference station isn't in the file
+    pub fn statistics(&self, reference_station: Option<&str>) -> Result<Statistics, Error> {
+        let legs: Vec<_> = self
+            .surveys()
+            .iter()
+            .flat_map(|survey| survey_legs(survey, Corrections::SURVEY))
+            .collect();
+        let anchors: Vec<_> = self
+            .fixed_stations()
+            .filter_map(|station| Some((station.name(), station.fix()?.location())))
+            .collect();
+        let coordinates = Traverse::new(
+            &legs,
+            &anchors,
+            EastNorthElevation::from_meters(0.0, 0.0, 0.0),
+        )
+        .coordinates();
+        collect(self.surveys(), &coordinates, reference_station)
+    }
+}
+
+impl Project<Loaded> {
+    /// Compute statistics for the whole project
+    /// Extents are measured from [`Project::station_coordinates`].
+    /// # Arguments
+    /// - `reference_station` - Station to measure the project's height and depth from, typically the entrance
+    /// # Errors
+    /// - [`Error::StationNotFound`] If the reference station isn't in the project
+    pub fn statistics(&self, reference_station: Option<&str>) -> Result<Statistics, Error> {
+        collect(
+            self.surveys().map(|(_, survey)| survey),
+            &self.station_coordinates(),
+            reference_station,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use float_eq::assert_float_eq;
+
+    use super::*;
+    use crate::{Parameters, ShotFlags};
+
+    #[test]
+    fn survey_statistics() {
+        let mut survey = Survey::new(
+            "Test Cave",
+            "T",
+            Date {
+                month: 5,
+                day: 6,
+                year: 2022,
+            },
+            "Ann Smith, Bo Jones;Cy Lee, ,",
+            Parameters {
+                declination: 0.0,
+                format: None,
+                correction_factors: None,
+                backsight_correction_factors: None,
+            },
+        );
+        let mut splay = Shot::new("T2", "T2a", 5.0, 270.0, 0.0);
+        splay.flags = Some(ShotFlags {
+            exclude_from_length: true,
+            ..ShotFlags::default()
+        });
+        let mut excluded = Shot::new("T3", "T4", 100.0, 0.0, 0.0);
+        excluded.flags = Some(ShotFlags {
+            exclude_from_processing: true,
+            ..ShotFlags::default()
+        });
+        survey.shots = vec![
+            Shot::new("T1", "T2", 10.0, 90.0, 0.0),
+            Shot::new("T2", "T3", 20.0, 0.0, -30.0),
+            splay,
+            excluded,
+        ];
+
+        let statistics = survey.statistics(Some("T2")).unwrap();
+        assert_float_eq!(statistics.length, 30.0 * FEET_TO_METERS, abs <= 1e-9);
+        assert_eq!(statistics.shot_count, 4);
+        assert_eq!(statistics.station_count, 4);
+        let (east_west, north_south) = statistics.horizontal_extent();
+        assert_float_eq!(east_west, 10.0 * FEET_TO_METERS, abs <= 1e-9);
+        assert_float_eq!(
+            north_south,
+            20.0 * 30f64.to_radians().cos() * FEET_TO_METERS,
+            abs <= 1e-9
+        );
+        assert_float_eq!(
+            statistics.vertical_range(),
+            10.0 * FEET_TO_METERS,
+            abs <= 1e-9
+        );
+        assert_float_eq!(statistics.height_above_reference.unwrap(), 0.0, abs <= 1e-9);
+        assert_float_eq!(
+            statistics.depth_below_reference.unwrap(),
+            10.0 * FEET_TO_METERS,
+            abs <= 1e-9
+        );
+        assert_eq!(statistics.first_date, Some(survey.date));
+        assert_eq!(
+            statistics.length_by_surveyor.keys().collect::<Vec<_>>(),
+            vec!["Ann Smith", "Bo Jones", "Cy Lee"]
+        );
+        assert_float_eq!(
+            statistics.length_by_year[&2022],
+            statistics.length,
+            abs <= 1e-9
+        );
+
+        assert!(matches!(
+            survey.statistics(Some("T4")),
+            Err(Error::StationNotFound(station)) if station == "T4"
+        ));
+    }
+
+    #[test]
+    fn two_digit_years() {
+        let survey = |year, shots| {
+            let mut survey = Survey::new(
+                "Test Cave",
+                "T",
+                Date {
+                    month: 7,
+                    day: 10,
+                    year,
+                },
+                "",
+                Parameters {
+                    declination: 0.0,
+                    format: None,
+                    correction_factors: None,
+                    backsight_correction_factors: None,
+                },
+            );
+            survey.shots = shots;
+            survey
+        };
+        let surveys = [
+            survey(1985, vec![Shot::new("T1", "T2", 10.0, 0.0, 0.0)]),
+            survey(79, vec![Shot::new("T2", "T3", 20.0, 0.0, 0.0)]),
+            survey(1979, vec![Shot::new("T3", "T4", 30.0, 0.0, 0.0)]),
+        ];
+
+        let statistics = collect(&surveys, &HashMap::new(), None).unwrap();
+        assert_eq!(statistics.first_date.unwrap().year, 1979);
+        assert_eq!(statistics.last_date.unwrap().year, 1985);
+        assert_eq!(
+            statistics.length_by_year.keys().collect::<Vec<_>>(),
+            vec![&1979, &1985]
+        );
+        assert_float_eq!(
+            statistics.length_by_year[&1979],
+            50.0 * FEET_TO_METERS,
+            abs <= 1e-9
+        );
+    }
+
+    #[test]
+    fn project_statistics() {
+        let mut sample_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sample_path.push("test_data/Fulfords.mak");
+        let project = Project::read(&sample_path)
+            .unwrap()
+            .load_survey_files()
+            .unwrap();
+        let statistics = project.statistics(Some("A1")).unwrap();
+        assert_float_eq!(statistics.length, 5997.60 * FEET_TO_METERS, abs <= 1e-6);
+        assert_eq!(statistics.shot_count, project.shots().count());
+        assert_eq!(
+            statistics.station_count,
+            project.station_coordinates().len()
+        );
+        assert_eq!(
+            statistics.first_date,
+            Some(Date {
+                month: 6,
+                day: 20,
+                year: 1987
+            })
+        );
+        assert_eq!(statistics.last_date.unwrap().year, 1989);
+        assert_float_eq!(
+            statistics.length_by_year[&1987],
+            1305.90 * FEET_TO_METERS,
+            abs <= 1e-6
+        );
+        assert_float_eq!(
+            statistics.length_by_year.values().sum::<f64>(),
+            statistics.length,
+            abs <= 1e-6
+        );
+        assert!(statistics.length_by_surveyor["Steve Reames"] > 0.0);
+        assert!(statistics.depth_below_reference.unwrap() > 0.0);
+
+        let fulsurf = project.survey_files[1].statistics(None).unwrap();
+        assert_float_eq!(fulsurf.length, 673.41 * FEET_TO_METERS, abs <= 1e-6);
+        assert_eq!(fulsurf.height_above_reference, None);
+    }
+}
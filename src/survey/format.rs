@@ -0,0 +1,156 @@
+//! Survey FORMAT string
+//!
+//! Compass always stores shot data in a fixed format (decimal feet and degrees),
+//! the FORMAT item records how the original survey notebook was laid out so editors can display it the same way.
+//! See the "File Format" section of `docs/survey_format.md` for the full description.
+
+/// Units used for bearings (azimuths) in the original notes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BearingUnits {
+    Degrees,
+    Quads,
+    Grads,
+}
+
+/// Units used for lengths and passage dimensions in the original notes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthUnits {
+    DecimalFeet,
+    FeetAndInches,
+    Meters,
+}
+
+/// Units used for inclinations in the original notes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InclinationUnits {
+    Degrees,
+    PercentGrade,
+    DegreesAndMinutes,
+    Grads,
+    DepthGauge,
+}
+
+/// A single passage dimension, used to describe the order LRUDs were recorded in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LrudItem {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A single shot measurement, used to describe the order shot items were recorded in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShotItem {
+    Length,
+    Azimuth,
+    Inclination,
+    BackAzimuth,
+    BackInclination,
+}
+
+/// Whether the survey was recorded with redundant backsights
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BacksightMode {
+    Redundant,
+    NotRedundant,
+}
+
+/// Which station of a shot the LRUDs were measured at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LrudAssociation {
+    From,
+    To,
+}
+
+/// The decoded contents of a survey's FORMAT item
+///
+/// Older versions of Compass wrote shorter format strings,
+/// so the items added in later versions are optional:
+/// - 11 characters: units, dimension order and 3 shot items
+/// - 12 characters: adds the backsight mode
+/// - 13 characters: adds the LRUD association
+/// - 15 characters: 5 shot items, including the backsight items
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurveyFormat {
+    pub bearing_units: BearingUnits,
+    pub length_units: LengthUnits,
+    pub passage_units: LengthUnits,
+    pub inclination_units: InclinationUnits,
+    pub passage_dimension_order: [LrudItem; 4],
+    pub shot_item_order: Vec<ShotItem>,
+    pub backsight_mode: Option<BacksightMode>,
+    pub lrud_association: Option<LrudAssociation>,
+}
+
+impl SurveyFormat {
+    /// Whether shots in this survey carry redundant backsight columns
+    #[must_use]
+    pub fn has_redundant_backsights(&self) -> bool {
+        self.backsight_mode == Some(BacksightMode::Redundant)
+    }
+
+    /// The station LRUDs are associated with, Compass assumes the from station when unspecified
+    #[must_use]
+    pub fn lrud_station(&self) -> LrudAssociation {
+        self.lrud_association.unwrap_or(LrudAssociation::From)
+    }
+
+    /// Serialize the format back to the characters used in the survey header
+    #[must_use]
+    pub fn serialize(&self) -> String {
+        let mut result = String::new();
+        result.push(match self.bearing_units {
+            BearingUnits::Degrees => 'D',
+            BearingUnits::Quads => 'Q',
+            BearingUnits::Grads => 'R',
+        });
+        result.push(length_units_char(self.length_units));
+        result.push(length_units_char(self.passage_units));
+        result.push(match self.inclination_units {
+            InclinationUnits::Degrees => 'D',
+            InclinationUnits::PercentGrade => 'G',
+            InclinationUnits::DegreesAndMinutes => 'M',
+            InclinationUnits::Grads => 'R',
+            InclinationUnits::DepthGauge => 'W',
+        });
+        for item in &self.passage_dimension_order {
+            result.push(match item {
+                LrudItem::Left => 'L',
+                LrudItem::Right => 'R',
+                LrudItem::Up => 'U',
+                LrudItem::Down => 'D',
+            });
+        }
+        for item in &self.shot_item_order {
+            result.push(match item {
+                ShotItem::Length => 'L',
+                ShotItem::Azimuth => 'A',
+                ShotItem::Inclination => 'D',
+                ShotItem::BackAzimuth => 'a',
+                ShotItem::BackInclination => 'd',
+            });
+        }
+        if let Some(backsight_mode) = self.backsight_mode {
+            result.push(match backsight_mode {
+                BacksightMode::Redundant => 'B',
+                BacksightMode::NotRedundant => 'N',
+            });
+        }
+        if let Some(lrud_association) = self.lrud_association {
+            result.push(match lrud_association {
+                LrudAssociation::From => 'F',
+                LrudAssociation::To => 'T',
+            });
+        }
+        result
+    }
+}
+
+fn length_units_char(units: LengthUnits) -> char {
+    match units {
+        LengthUnits::DecimalFeet => 'D',
+        LengthUnits::FeetAndInches => 'I',
+        LengthUnits::Meters => 'M',
+    }
+}
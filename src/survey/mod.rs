@@ -1,6 +1,41 @@
+use std::{fmt::Write as _, io::Write};
+
 use crate::{common_types::Date, Error};
+use serializer::SurveySource;
 
+mod format;
 mod parser;
+mod serializer;
+
+pub use format::{
+    BacksightMode, BearingUnits, InclinationUnits, LengthUnits, LrudAssociation, LrudItem,
+    ShotItem, SurveyFormat,
+};
+
+/// Line endings used when writing survey files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Carriage return and line feed, as written by Compass
+    #[default]
+    CrLf,
+    Lf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf => "\n",
+        }
+    }
+}
+
+/// Compass marks azimuth and inclination readings which weren't taken with this value
+const MISSING_ANGLE: f64 = -999.0;
+/// Compass marks passage dimensions which weren't taken with this value
+const MISSING_DIMENSION: f64 = -9999.0;
+/// Any negative passage dimension above the missing sentinels means the wall was too far to measure
+const PASSAGE_CONTINUES: f64 = -1.0;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CorrectionFactors {
@@ -18,49 +53,206 @@ pub struct BackSightCorrectionFactors {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Parameters {
     pub declination: f64,
+    /// The layout of the original survey notes, `None` if the survey has no FORMAT item
+    pub format: Option<SurveyFormat>,
     pub correction_factors: Option<CorrectionFactors>,
     pub backsight_correction_factors: Option<BackSightCorrectionFactors>,
 }
 
 impl Parameters {
+    /// Whether shots recorded with these parameters carry redundant backsight columns
+    #[must_use]
+    pub fn has_redundant_backsights(&self) -> bool {
+        self.format
+            .as_ref()
+            .is_some_and(SurveyFormat::has_redundant_backsights)
+    }
+
     fn serialize(&self) -> String {
-        let mut result = String::new();
-        result.push_str(&format!("DECLINATION:   {:>4.2}  ", self.declination));
+        let mut result = format!("DECLINATION:   {:>4.2}  ", self.declination);
+        if let Some(format) = &self.format {
+            let _ = write!(result, "FORMAT: {}  ", format.serialize());
+        }
         if let Some(correction_factors) = &self.correction_factors {
-            result.push_str(&format!(
+            let _ = write!(
+                result,
                 "CORRECTIONS:  {:.2} {:.2} {:.2}",
                 correction_factors.azimuth,
                 correction_factors.inclination,
                 correction_factors.length
-            ));
+            );
         }
         if let Some(backsight_correction_factors) = &self.backsight_correction_factors {
-            result.push_str(&format!(
+            let _ = write!(
+                result,
                 "CORRECTIONS2: {:.1} {:.1}",
                 backsight_correction_factors.azimuth, backsight_correction_factors.inclination
-            ));
+            );
+        }
+        result
+    }
+}
+
+/// Flags modifying how a shot is processed, written as `#|...#` after the shot measurements
+// Each field is one of Compass' flag letters, which are set independently of each other
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShotFlags {
+    /// L - Exclude this shot from length calculations
+    pub exclude_from_length: bool,
+    /// P - Exclude this shot from plotting
+    pub exclude_from_plotting: bool,
+    /// X - Exclude this shot from all processing
+    pub exclude_from_processing: bool,
+    /// C - Do not adjust this shot when closing loops
+    pub do_not_adjust: bool,
+    /// Any flag characters Compass doesn't define, kept so they aren't lost when the shot is written
+    pub unknown: String,
+}
+
+impl ShotFlags {
+    /// Serialize the flags to the `#|...#` form used in survey files
+    #[must_use]
+    pub fn serialize(&self) -> String {
+        let mut result = String::from("#|");
+        if self.exclude_from_length {
+            result.push('L');
+        }
+        if self.exclude_from_plotting {
+            result.push('P');
+        }
+        if self.exclude_from_processing {
+            result.push('X');
+        }
+        if self.do_not_adjust {
+            result.push('C');
         }
-        result.push_str("\r\n");
+        result.push_str(&self.unknown);
+        result.push('#');
         result
     }
 }
 
+/// A reading on a shot which may not have been taken
+///
+/// Compass stores these as plain numbers, using negative values and sentinels for missing data.
+/// The sentinels are mapped to variants when parsing, keeping the number that was stored
+/// so it is written back unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Measurement {
+    /// The value that was read
+    Measured(f64),
+    /// No reading was taken, with the sentinel Compass stored, usually -999 or -9999
+    Missing(f64),
+    /// The passage continues too far for a wall to be measured, only used for passage dimensions
+    /// Holds the negative value Compass stored
+    PassageContinues(f64),
+}
+
+impl Measurement {
+    /// A missing azimuth or inclination, as Compass writes it
+    pub const MISSING_ANGLE: Self = Self::Missing(MISSING_ANGLE);
+    /// A missing passage dimension, as Compass writes it
+    pub const MISSING_DIMENSION: Self = Self::Missing(MISSING_DIMENSION);
+    /// A passage dimension too far to measure, as Compass writes it
+    pub const PASSAGE_CONTINUES: Self = Self::PassageContinues(PASSAGE_CONTINUES);
+
+    /// The measured value, if there is one
+    #[must_use]
+    pub fn value(self) -> Option<f64> {
+        match self {
+            Measurement::Measured(value) => Some(value),
+            Measurement::Missing(_) | Measurement::PassageContinues(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_measured(self) -> bool {
+        matches!(self, Measurement::Measured(_))
+    }
+
+    /// Interpret an azimuth or inclination as stored by Compass
+    pub(crate) fn from_compass_angle(value: f64) -> Self {
+        if value <= MISSING_ANGLE {
+            Measurement::Missing(value)
+        } else {
+            Measurement::Measured(value)
+        }
+    }
+
+    /// Interpret a passage dimension as stored by Compass
+    /// Both the -999 and -9999 sentinels are used for missing dimensions in the wild
+    pub(crate) fn from_compass_dimension(value: f64) -> Self {
+        if value <= MISSING_ANGLE {
+            Measurement::Missing(value)
+        } else if value < 0.0 {
+            Measurement::PassageContinues(value)
+        } else {
+            Measurement::Measured(value)
+        }
+    }
+
+    /// The number Compass stores for the reading
+    pub(crate) fn to_compass(self) -> f64 {
+        match self {
+            Measurement::Measured(value)
+            | Measurement::Missing(value)
+            | Measurement::PassageContinues(value) => value,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Shot {
     pub from: String,
     pub to: String,
     pub length: f64,
-    pub azimuth: f64,
-    pub inclination: f64,
-    pub up: f64,
-    pub down: f64,
-    pub left: f64,
-    pub right: f64,
-    pub flags: Option<String>,
+    pub azimuth: Measurement,
+    pub inclination: Measurement,
+    pub up: Measurement,
+    pub down: Measurement,
+    pub left: Measurement,
+    pub right: Measurement,
+    /// Redundant backsight azimuth, stored uncorrected
+    /// Always missing unless the survey format has redundant backsights
+    pub backsight_azimuth: Measurement,
+    /// Redundant backsight inclination, stored uncorrected
+    /// Always missing unless the survey format has redundant backsights
+    pub backsight_inclination: Measurement,
+    pub flags: Option<ShotFlags>,
     pub comment: Option<String>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl Shot {
+    /// Create a shot from its tape, compass and clino readings
+    /// Passage dimensions and backsights start out missing, and the shot has no flags or comment
+    #[must_use]
+    pub fn new(
+        from: impl Into<String>,
+        to: impl Into<String>,
+        length: f64,
+        azimuth: f64,
+        inclination: f64,
+    ) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+            length,
+            azimuth: Measurement::Measured(azimuth),
+            inclination: Measurement::Measured(inclination),
+            up: Measurement::MISSING_DIMENSION,
+            down: Measurement::MISSING_DIMENSION,
+            left: Measurement::MISSING_DIMENSION,
+            right: Measurement::MISSING_DIMENSION,
+            backsight_azimuth: Measurement::MISSING_ANGLE,
+            backsight_inclination: Measurement::MISSING_ANGLE,
+            flags: None,
+            comment: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Survey {
     pub cave_name: String,
     pub name: String,
@@ -69,9 +261,45 @@ pub struct Survey {
     pub team: String,
     pub parameters: Parameters,
     pub shots: Vec<Shot>,
+    /// The text the survey was parsed from, used for lossless serialization
+    source: Option<SurveySource>,
+}
+
+/// Surveys are compared by their data, regardless of the text they were parsed from
+impl PartialEq for Survey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cave_name == other.cave_name
+            && self.name == other.name
+            && self.date == other.date
+            && self.comment == other.comment
+            && self.team == other.team
+            && self.parameters == other.parameters
+            && self.shots == other.shots
+    }
 }
 
 impl Survey {
+    /// Create a survey without any shots
+    #[must_use]
+    pub fn new(
+        cave_name: impl Into<String>,
+        name: impl Into<String>,
+        date: Date,
+        team: impl Into<String>,
+        parameters: Parameters,
+    ) -> Self {
+        Self {
+            cave_name: cave_name.into(),
+            name: name.into(),
+            date,
+            comment: None,
+            team: team.into(),
+            parameters,
+            shots: Vec::new(),
+            source: None,
+        }
+    }
+
     /// Parse a survey from a string
     /// # Arguments
     /// input - A string containing the survey data
@@ -100,39 +328,60 @@ impl Survey {
         }
     }
 
+    /// Serialize the survey in the fixed layout Compass writes, with CRLF line endings
+    /// Formatting details of a parsed survey are not preserved, see [`Survey::serialize_lossless`]
     #[must_use]
     pub fn serialize(&self) -> String {
-        let mut result = String::new();
-        result.push_str(&format!("{}\r\n", self.cave_name));
-        result.push_str(&format!("SURVEY NAME: {}\n", self.name));
-        result.push_str(&format!(
-            "SURVEY DATE: {} {} {}",
-            self.date.month, self.date.day, self.date.year
-        ));
-        if let Some(comment) = &self.comment {
-            result.push_str(&format!("  COMMENT:{comment}\r\n"));
-        } else {
-            result.push_str("\r\n");
-        }
-        result.push_str("SURVEY TEAM: \r\n");
-        result.push_str(&format!("{}\r\n", self.team));
-        result.push_str(&self.parameters.serialize());
-        result.push_str("\n        FROM           TO   LENGTH  BEARING      INC     LEFT       UP     DOWN    RIGHT   FLAGS  COMMENTS\n\n");
-        for shot in &self.shots {
-            result.push_str(&format!(
-                "{:>12}{:>13}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}\n",
-                shot.from,
-                shot.to,
-                shot.length,
-                shot.azimuth,
-                shot.inclination,
-                shot.up,
-                shot.down,
-                shot.left,
-                shot.right
-            ));
-        }
-        result.push_str("\x0c\n");
-        result
+        self.serialize_with_line_ending(LineEnding::CrLf)
+    }
+
+    /// Serialize the survey in the fixed layout Compass writes, with the given line endings
+    #[must_use]
+    pub fn serialize_with_line_ending(&self, line_ending: LineEnding) -> String {
+        serializer::serialize_survey(self, line_ending.as_str())
+    }
+
+    /// Serialize the survey, reproducing the text it was parsed from wherever the data is unchanged
+    ///
+    /// Lines which have been modified since parsing, and surveys created programmatically,
+    /// are written in the same layout as [`Survey::serialize`],
+    /// using the line endings of the original file.
+    #[must_use]
+    pub fn serialize_lossless(&self) -> String {
+        serializer::serialize_survey_lossless(self)
+    }
+
+    /// Serialize the contents of a survey.dat file
+    /// Counterpart to [`Survey::parse_dat_file`], parsing a file and serializing the result
+    /// reproduces the original file byte for byte
+    #[must_use]
+    pub fn serialize_dat_file(surveys: &[Self]) -> String {
+        surveys.iter().map(Survey::serialize_lossless).collect()
+    }
+
+    /// Format a complete survey.dat file in the fixed layout Compass writes
+    /// Every survey is terminated by a form feed on its own line, and all lines use the same ending
+    #[must_use]
+    pub fn format_dat_file(surveys: &[Self], line_ending: LineEnding) -> String {
+        surveys
+            .iter()
+            .map(|survey| survey.serialize_with_line_ending(line_ending))
+            .collect()
+    }
+
+    /// Write a complete survey.dat file in the fixed layout Compass writes
+    /// # Errors
+    /// - [`Error::CouldntWriteFile`] If writing to `writer` fails
+    pub fn write_dat_file(
+        surveys: &[Self],
+        writer: &mut impl Write,
+        line_ending: LineEnding,
+    ) -> Result<(), Error> {
+        for survey in surveys {
+            writer
+                .write_all(survey.serialize_with_line_ending(line_ending).as_bytes())
+                .map_err(Error::CouldntWriteFile)?;
+        }
+        writer.flush().map_err(Error::CouldntWriteFile)
     }
 }
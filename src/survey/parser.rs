@@ -1,9 +1,10 @@
 use nom::{
-    bytes::complete::{tag, take_till1},
-    character::complete::{alpha1, multispace0, multispace1},
+    bytes::complete::{tag, take_till, take_till1, take_while},
+    character::complete::{alpha1, char, line_ending, multispace0, multispace1, space0},
+    combinator::{consumed, map_opt, opt, recognize},
     error::Error,
     multi::many0,
-    sequence::Tuple,
+    sequence::{tuple, Tuple},
     IResult, Parser,
 };
 
@@ -12,23 +13,28 @@ use crate::{
     parser_utils::{parse_double, parse_station_name, parse_uint, recognize_line, ws},
 };
 
-use super::{BackSightCorrectionFactors, CorrectionFactors, Parameters, Shot, Survey};
+use super::{
+    serializer::SurveySource, BackSightCorrectionFactors, BacksightMode, BearingUnits,
+    CorrectionFactors, InclinationUnits, LengthUnits, LrudAssociation, LrudItem, Measurement,
+    Parameters, Shot, ShotFlags, ShotItem, Survey, SurveyFormat,
+};
 
-fn parse_cave_name(input: &str) -> IResult<&str, String> {
+pub(super) fn parse_cave_name(input: &str) -> IResult<&str, String> {
     let (input, cave_name) = recognize_line(input)?;
     let (cave_name, _) = multispace0(cave_name)?;
     Ok((input, cave_name.to_string()))
 }
 
-fn parse_survey_name(input: &str) -> IResult<&str, String> {
+pub(super) fn parse_survey_name(input: &str) -> IResult<&str, String> {
     let (input, survey_line) = recognize_line(input)?;
     let (name, _) = tag("SURVEY NAME:")(survey_line)?;
-    let (_, name) = parse_station_name(name)?;
+    // Unlike station names, survey names can contain any printable character
+    let (_, name) = ws(take_till1(char::is_whitespace)).parse(name)?;
 
     Ok((input, name.to_string()))
 }
 
-fn parse_survey_date_line(input: &str) -> IResult<&str, (Date, Option<String>)> {
+pub(super) fn parse_survey_date_line(input: &str) -> IResult<&str, (Date, Option<String>)> {
     let (input, date_line) = recognize_line(input)?;
     let (date_line, _) = tag("SURVEY DATE:")(date_line)?;
     let (date_line, month) = parse_uint(date_line)?;
@@ -47,7 +53,7 @@ fn parse_survey_date_line(input: &str) -> IResult<&str, (Date, Option<String>)>
     Ok((input, (date, comment)))
 }
 
-fn parse_survey_team(input: &str) -> IResult<&str, String> {
+pub(super) fn parse_survey_team(input: &str) -> IResult<&str, String> {
     let (input, _) = (tag("SURVEY TEAM:"), multispace1).parse(input)?;
     let (input, team_line) = recognize_line(input)?;
     Ok((input, team_line.to_string()))
@@ -81,14 +87,106 @@ fn parse_backsight_correction_factors(input: &str) -> IResult<&str, BackSightCor
     ))
 }
 
-fn parse_survey_parameters(input: &str) -> IResult<&str, Parameters> {
+fn decode_length_units(c: char) -> Option<LengthUnits> {
+    match c {
+        'D' => Some(LengthUnits::DecimalFeet),
+        'I' => Some(LengthUnits::FeetAndInches),
+        'M' => Some(LengthUnits::Meters),
+        _ => None,
+    }
+}
+
+fn decode_lrud_item(c: char) -> Option<LrudItem> {
+    match c {
+        'L' => Some(LrudItem::Left),
+        'R' => Some(LrudItem::Right),
+        'U' => Some(LrudItem::Up),
+        'D' => Some(LrudItem::Down),
+        _ => None,
+    }
+}
+
+fn decode_shot_item(c: char) -> Option<ShotItem> {
+    match c {
+        'L' => Some(ShotItem::Length),
+        'A' => Some(ShotItem::Azimuth),
+        'D' => Some(ShotItem::Inclination),
+        'a' => Some(ShotItem::BackAzimuth),
+        'd' => Some(ShotItem::BackInclination),
+        _ => None,
+    }
+}
+
+/// Decode the 11, 12, 13, or 15 character format strings written by different versions of Compass
+fn decode_survey_format(format: &str) -> Option<SurveyFormat> {
+    let chars: Vec<char> = format.chars().collect();
+    let shot_item_count = match chars.len() {
+        11..=13 => 3,
+        15 => 5,
+        _ => return None,
+    };
+    let bearing_units = match chars[0] {
+        'D' => BearingUnits::Degrees,
+        'Q' => BearingUnits::Quads,
+        'R' => BearingUnits::Grads,
+        _ => return None,
+    };
+    let length_units = decode_length_units(chars[1])?;
+    let passage_units = decode_length_units(chars[2])?;
+    let inclination_units = match chars[3] {
+        'D' => InclinationUnits::Degrees,
+        'G' => InclinationUnits::PercentGrade,
+        'M' => InclinationUnits::DegreesAndMinutes,
+        'R' => InclinationUnits::Grads,
+        'W' => InclinationUnits::DepthGauge,
+        _ => return None,
+    };
+    let passage_dimension_order = [
+        decode_lrud_item(chars[4])?,
+        decode_lrud_item(chars[5])?,
+        decode_lrud_item(chars[6])?,
+        decode_lrud_item(chars[7])?,
+    ];
+    let shot_items_end = 8 + shot_item_count;
+    let shot_item_order = chars[8..shot_items_end]
+        .iter()
+        .map(|c| decode_shot_item(*c))
+        .collect::<Option<Vec<_>>>()?;
+    let backsight_mode = match chars.get(shot_items_end) {
+        Some('B') => Some(BacksightMode::Redundant),
+        Some('N') => Some(BacksightMode::NotRedundant),
+        None => None,
+        Some(_) => return None,
+    };
+    let lrud_association = match chars.get(shot_items_end + 1) {
+        Some('F') => Some(LrudAssociation::From),
+        Some('T') => Some(LrudAssociation::To),
+        None => None,
+        Some(_) => return None,
+    };
+    Some(SurveyFormat {
+        bearing_units,
+        length_units,
+        passage_units,
+        inclination_units,
+        passage_dimension_order,
+        shot_item_order,
+        backsight_mode,
+        lrud_association,
+    })
+}
+
+fn parse_survey_format(input: &str) -> IResult<&str, SurveyFormat> {
+    let (input, _) = tag("FORMAT:")(input)?;
+    let (input, format) = map_opt(ws(alpha1), decode_survey_format).parse(input)?;
+    Ok((input, format))
+}
+
+pub(super) fn parse_survey_parameters(input: &str) -> IResult<&str, Parameters> {
     let (input, parameter_line) = recognize_line(input)?;
     let (parameter_line, _) = tag("DECLINATION:")(parameter_line)?;
     let (parameter_line, declination) = parse_double(parameter_line)?;
-    let (parameter_line, _) = tag("FORMAT:")(parameter_line)?;
-    let (parameter_line, _) = multispace0(parameter_line)?;
-    let (parameter_line, _) = alpha1(parameter_line)?;
-    let (parameter_line, _) = multispace0(parameter_line)?;
+    let (parameter_line, format) = opt(parse_survey_format)(parameter_line)?;
     let correction_factor_result = parse_correction_factors(parameter_line);
     let (parameter_line, correction_factors) = match correction_factor_result {
         Ok((input, correction_factors)) => (input, Some(correction_factors)),
@@ -104,29 +202,88 @@ fn parse_survey_parameters(input: &str) -> IResult<&str, Parameters> {
         input,
         Parameters {
             declination,
+            format,
             correction_factors,
             backsight_correction_factors,
         },
     ))
 }
 
+fn blank_line(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((space0, line_ending)))(input)
+}
+
+/// The column labels are only there for readability, so consume them along with the surrounding blank lines
 fn gobble_labels(input: &str) -> IResult<&str, &str> {
-    let (input, _) = ws(tag("FROM")).parse(input)?;
-    let (input, _) = ws(take_till1(|c| c == '\n')).parse(input)?;
-    Ok((input, ""))
+    recognize(tuple((
+        many0(blank_line),
+        space0,
+        tag("FROM"),
+        recognize_line,
+        many0(blank_line),
+    )))(input)
+}
+
+/// Surveys end with a form feed, and the last one in a file may be followed by an end of file marker
+fn gobble_survey_terminator(input: &str) -> IResult<&str, &str> {
+    take_while(|c| matches!(c, ' ' | '\t' | '\r' | '\n' | '\x0c' | '\x1a'))(input)
+}
+
+fn parse_angle(input: &str) -> IResult<&str, Measurement> {
+    let (input, value) = parse_double(input)?;
+    Ok((input, Measurement::from_compass_angle(value)))
 }
 
-fn parse_shot(input: &str) -> IResult<&str, Shot> {
+fn parse_dimension(input: &str) -> IResult<&str, Measurement> {
+    let (input, value) = parse_double(input)?;
+    Ok((input, Measurement::from_compass_dimension(value)))
+}
+
+fn parse_shot_flags(input: &str) -> IResult<&str, ShotFlags> {
+    let (input, _) = tag("#|")(input)?;
+    let (input, flag_chars) = take_till(|c| c == '#')(input)?;
+    let (input, _) = char('#')(input)?;
+    let mut flags = ShotFlags::default();
+    for flag in flag_chars.chars() {
+        match flag {
+            'L' => flags.exclude_from_length = true,
+            'P' => flags.exclude_from_plotting = true,
+            'X' => flags.exclude_from_processing = true,
+            'C' => flags.do_not_adjust = true,
+            unknown => flags.unknown.push(unknown),
+        }
+    }
+    Ok((input, flags))
+}
+
+pub(super) fn parse_shot(input: &str, redundant_backsights: bool) -> IResult<&str, Shot> {
     let (input, line) = recognize_line(input)?;
     let (line, from) = parse_station_name(line)?;
     let (line, to) = parse_station_name(line)?;
     let (line, length) = parse_double(line)?;
-    let (line, azimuth) = parse_double(line)?;
-    let (line, inclination) = parse_double(line)?;
-    let (line, left) = parse_double(line)?;
-    let (line, up) = parse_double(line)?;
-    let (line, down) = parse_double(line)?;
-    let (_, right) = parse_double(line)?;
+    let (line, azimuth) = parse_angle(line)?;
+    let (line, inclination) = parse_angle(line)?;
+    let (line, left) = parse_dimension(line)?;
+    let (line, up) = parse_dimension(line)?;
+    let (line, down) = parse_dimension(line)?;
+    let (line, right) = parse_dimension(line)?;
+    let (line, (backsight_azimuth, backsight_inclination)) = if redundant_backsights {
+        let (line, backsight_azimuth) = parse_angle(line)?;
+        let (line, backsight_inclination) = parse_angle(line)?;
+        (line, (backsight_azimuth, backsight_inclination))
+    } else {
+        (
+            line,
+            (Measurement::MISSING_ANGLE, Measurement::MISSING_ANGLE),
+        )
+    };
+    let (line, flags) = opt(parse_shot_flags)(line)?;
+    let comment = line.trim();
+    let comment = if comment.is_empty() {
+        None
+    } else {
+        Some(comment.to_string())
+    };
     let shot = Shot {
         from: from.to_string(),
         to: to.to_string(),
@@ -137,21 +294,38 @@ fn parse_shot(input: &str) -> IResult<&str, Shot> {
         down,
         left,
         right,
-        flags: None,
-        comment: None,
+        backsight_azimuth,
+        backsight_inclination,
+        flags,
+        comment,
     };
     Ok((input, shot))
 }
 
 pub(crate) fn parse_survey(input: &str) -> IResult<&str, Survey> {
-    let (input, cave_name) = parse_cave_name(input)?;
-    let (input, name) = parse_survey_name(input)?;
-    let (input, (date, comment)) = parse_survey_date_line(input)?;
-    let (input, team) = parse_survey_team(input)?;
-    let (input, parameters) = parse_survey_parameters(input)?;
-    let (input, _) = gobble_labels(input)?;
-    let (input, shots) = many0(parse_shot)(input)?;
-    let (input, _) = ws(tag("")).parse(input)?;
+    let (input, (cave_name_source, cave_name)) = consumed(parse_cave_name)(input)?;
+    let (input, (name_source, name)) = consumed(parse_survey_name)(input)?;
+    let (input, (date_source, (date, comment))) = consumed(parse_survey_date_line)(input)?;
+    let (input, (team_source, team)) = consumed(parse_survey_team)(input)?;
+    let (input, (parameters_source, parameters)) = consumed(parse_survey_parameters)(input)?;
+    let (input, column_header) = gobble_labels(input)?;
+    let redundant_backsights = parameters.has_redundant_backsights();
+    let (input, shots) = many0(consumed(|input| parse_shot(input, redundant_backsights)))(input)?;
+    let (input, trailer) = gobble_survey_terminator(input)?;
+    let (shot_sources, shots) = shots
+        .into_iter()
+        .map(|(source, shot)| (source.to_string(), shot))
+        .unzip();
+    let source = SurveySource {
+        cave_name: cave_name_source.to_string(),
+        name: name_source.to_string(),
+        date: date_source.to_string(),
+        team: team_source.to_string(),
+        parameters: parameters_source.to_string(),
+        column_header: column_header.to_string(),
+        shots: shot_sources,
+        trailer: trailer.to_string(),
+    };
     Ok((
         input,
         Survey {
@@ -162,6 +336,7 @@ pub(crate) fn parse_survey(input: &str) -> IResult<&str, Survey> {
             team,
             parameters,
             shots,
+            source: Some(source),
         },
     ))
 }
@@ -173,6 +348,8 @@ pub fn parse_dat_file(input: &str) -> IResult<&str, Vec<Survey>> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::LineEnding;
+    use float_eq::assert_float_eq;
     use pretty_assertions::assert_str_eq;
 
     #[test]
@@ -181,10 +358,7 @@ mod test {
         let (_input, surveys) = many0(parse_survey)(input).unwrap();
 
         for survey in &surveys {
-            // We have at least one gap in the serialization (FORMAT), so for now just do a test
-            // against a simplified survey
-            // Eventually we should be able to just do
-            // `assert_str_eq!(survey.serialize(), input)`
+            // The normalized serialization matches Compass's own layout for this survey
             if survey.name == "CL" {
                 let perfection = include_str!("../../test_data/fulford_cave_survey.dat").trim();
                 assert_str_eq!(
@@ -196,4 +370,302 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn parse_format_versions() {
+        let (_, format) = parse_survey_format("FORMAT: DDDDUDLRLAD").unwrap();
+        assert_eq!(format.bearing_units, BearingUnits::Degrees);
+        assert_eq!(format.length_units, LengthUnits::DecimalFeet);
+        assert_eq!(format.inclination_units, InclinationUnits::Degrees);
+        assert_eq!(
+            format.passage_dimension_order,
+            [
+                LrudItem::Up,
+                LrudItem::Down,
+                LrudItem::Left,
+                LrudItem::Right
+            ]
+        );
+        assert_eq!(
+            format.shot_item_order,
+            vec![ShotItem::Length, ShotItem::Azimuth, ShotItem::Inclination]
+        );
+        assert_eq!(format.backsight_mode, None);
+        assert_eq!(format.lrud_association, None);
+        assert_eq!(format.lrud_station(), LrudAssociation::From);
+
+        let (_, format) = parse_survey_format("FORMAT: DDDDUDLRLADN").unwrap();
+        assert_eq!(format.backsight_mode, Some(BacksightMode::NotRedundant));
+        assert!(!format.has_redundant_backsights());
+
+        let (_, format) = parse_survey_format("FORMAT: QMIGLRUDDALBT").unwrap();
+        assert_eq!(format.bearing_units, BearingUnits::Quads);
+        assert_eq!(format.length_units, LengthUnits::Meters);
+        assert_eq!(format.passage_units, LengthUnits::FeetAndInches);
+        assert_eq!(format.inclination_units, InclinationUnits::PercentGrade);
+        assert!(format.has_redundant_backsights());
+        assert_eq!(format.lrud_station(), LrudAssociation::To);
+
+        let (_, format) = parse_survey_format("FORMAT: RDDWLRUDLADadBF").unwrap();
+        assert_eq!(format.bearing_units, BearingUnits::Grads);
+        assert_eq!(format.inclination_units, InclinationUnits::DepthGauge);
+        assert_eq!(
+            format.shot_item_order,
+            vec![
+                ShotItem::Length,
+                ShotItem::Azimuth,
+                ShotItem::Inclination,
+                ShotItem::BackAzimuth,
+                ShotItem::BackInclination
+            ]
+        );
+        assert_eq!(format.serialize(), "RDDWLRUDLADadBF");
+
+        assert!(parse_survey_format("FORMAT: DDDDUDLRLADNFX").is_err());
+        assert!(parse_survey_format("FORMAT: XDDDUDLRLADN").is_err());
+        assert!(parse_survey_format("FORMAT: DDDDUDLRLADX").is_err());
+    }
+
+    #[test]
+    fn parse_parameters_without_format() {
+        let (_, parameters) =
+            parse_survey_parameters("DECLINATION:   11.18  CORRECTIONS:  0.00 0.00 0.00\n")
+                .unwrap();
+        assert_eq!(parameters.format, None);
+        assert!(parameters.correction_factors.is_some());
+
+        let (_, parameters) = parse_survey_parameters(
+            "DECLINATION:   11.18  FORMAT: DDDDUDLRLADN  CORRECTIONS:  0.00 0.00 0.00\n",
+        )
+        .unwrap();
+        assert_eq!(
+            parameters.format.map(|format| format.serialize()),
+            Some("DDDDUDLRLADN".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_redundant_backsights() {
+        let input = "SECRET CAVE
+SURVEY NAME: B
+SURVEY DATE: 7 10 79  COMMENT:Big Room Survey
+SURVEY TEAM:
+D.SMITH,R.BROWN,S.MURRAY
+DECLINATION: 1.00  FORMAT: DDDDLUDRLADBF  CORRECTIONS: 2.00 3.00 4.00 CORRECTIONS2: 5.0 6.0
+
+        FROM           TO   LENGTH  BEARING      INC     LEFT       UP     DOWN    RIGHT     AZM2     INC2   FLAGS  COMMENTS
+
+          B2           B1    13.00    35.00    15.00    -9.90     2.00     1.50     1.00   215.00   -15.00
+          B2           B3    22.10    16.00    22.00     6.00     1.00     0.00     2.00  -999.00   -22.00  #|PC# Side Passage
+\x0c
+";
+        let (_, survey) = parse_survey(input).unwrap();
+        assert_eq!(survey.shots.len(), 2);
+        assert_eq!(
+            survey.shots[0].backsight_azimuth,
+            Measurement::Measured(215.0)
+        );
+        assert_eq!(
+            survey.shots[0].backsight_inclination,
+            Measurement::Measured(-15.0)
+        );
+        assert_eq!(survey.shots[0].left, Measurement::PassageContinues(-9.9));
+        assert_eq!(
+            survey.shots[1].backsight_azimuth,
+            Measurement::MISSING_ANGLE
+        );
+        assert_eq!(
+            survey.shots[1].backsight_inclination,
+            Measurement::Measured(-22.0)
+        );
+
+        let (_, reparsed) = parse_survey(&survey.serialize()).unwrap();
+        assert_eq!(reparsed.shots, survey.shots);
+        assert_eq!(survey.shots[1].flags.as_ref().unwrap().serialize(), "#|PC#");
+        assert_eq!(survey.shots[1].comment.as_deref(), Some("Side Passage"));
+    }
+
+    #[test]
+    fn parse_flags_and_comments() {
+        let (_, shot) = parse_shot(
+            "          A2           A3    41.17    46.00     2.00     0.00     0.00     0.00     0.00  #|PC# Room\n",
+            false,
+        )
+        .unwrap();
+        let flags = shot.flags.unwrap();
+        assert!(flags.exclude_from_plotting);
+        assert!(flags.do_not_adjust);
+        assert!(!flags.exclude_from_length);
+        assert!(!flags.exclude_from_processing);
+        assert_eq!(shot.comment.as_deref(), Some("Room"));
+
+        let (_, shot) = parse_shot(
+            "          A2           A1    12.00   135.00     5.00     0.00     4.00     0.50     0.00  Big Room\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(shot.flags, None);
+        assert_eq!(shot.comment.as_deref(), Some("Big Room"));
+
+        let (_, shot) = parse_shot(
+            "          A4           A5    22.50   129.00   -21.00     0.00     0.00     0.00     0.00  #|LXZ#\n",
+            false,
+        )
+        .unwrap();
+        let flags = shot.flags.unwrap();
+        assert!(flags.exclude_from_length);
+        assert!(flags.exclude_from_processing);
+        assert_eq!(flags.unknown, "Z");
+        assert_eq!(flags.serialize(), "#|LXZ#");
+        assert_eq!(shot.comment, None);
+    }
+
+    #[test]
+    fn parse_missing_measurements() {
+        let input = include_str!("../../test_data/Fulford.dat");
+        let (_, survey) = parse_survey(input).unwrap();
+        let shot = &survey.shots[3];
+        assert_eq!(shot.from, "A4");
+        assert_eq!(shot.left, Measurement::MISSING_DIMENSION);
+        assert_eq!(shot.up, Measurement::Measured(6.0));
+        assert_eq!(shot.right, Measurement::MISSING_DIMENSION);
+        assert_eq!(shot.backsight_azimuth, Measurement::MISSING_ANGLE);
+
+        assert_eq!(
+            Measurement::from_compass_angle(-999.0),
+            Measurement::MISSING_ANGLE
+        );
+        assert_eq!(
+            Measurement::from_compass_angle(-45.0),
+            Measurement::Measured(-45.0)
+        );
+        assert_eq!(
+            Measurement::from_compass_dimension(-2.0),
+            Measurement::PassageContinues(-2.0)
+        );
+        // Each sentinel is written back as it was read
+        for value in [-999.0, -9999.0, -1.0, -2.0, 3.5] {
+            assert_float_eq!(
+                Measurement::from_compass_dimension(value).to_compass(),
+                value,
+                abs <= f64::EPSILON
+            );
+        }
+        assert_eq!(Measurement::from_compass_dimension(-999.0).value(), None);
+    }
+
+    #[test]
+    fn edited_shots_keep_their_sentinels() {
+        let input = "\
+SECRET CAVE
+SURVEY NAME: A
+SURVEY DATE: 7 10 79
+SURVEY TEAM:
+D.SMITH
+DECLINATION: 1.00  FORMAT: DDDDUDLRLADN
+
+        FROM           TO   LENGTH  BEARING      INC     LEFT       UP     DOWN    RIGHT   FLAGS  COMMENTS
+
+          A1           A2    10.00    90.00     0.00    -2.00  -999.00  -9999.00     3.00
+\x0c
+";
+        let (_, mut survey) = parse_survey(input).unwrap();
+        survey.shots[0].length = 12.5;
+        let (_, reparsed) = parse_survey(&survey.serialize()).unwrap();
+        let shot = &reparsed.shots[0];
+        assert_eq!(shot.left, Measurement::PassageContinues(-2.0));
+        assert_eq!(shot.up, Measurement::Missing(-999.0));
+        assert_eq!(shot.down, Measurement::MISSING_DIMENSION);
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        for input in [
+            include_str!("../../test_data/Fulford.dat"),
+            include_str!("../../test_data/Fulsurf.dat"),
+        ] {
+            let surveys = Survey::parse_dat_file(input).unwrap();
+            assert_str_eq!(Survey::serialize_dat_file(&surveys), input);
+
+            let crlf_input = input.replace("\r\n", "\n").replace('\n', "\r\n");
+            let surveys = Survey::parse_dat_file(&crlf_input).unwrap();
+            assert_str_eq!(Survey::serialize_dat_file(&surveys), crlf_input);
+        }
+    }
+
+    #[test]
+    fn lossless_round_trip_with_edits() {
+        let input = include_str!("../../test_data/Fulford.dat");
+        let mut surveys = Survey::parse_dat_file(input).unwrap();
+        assert_eq!(surveys[1].name, "A+");
+        surveys[0].shots[0].length = 21.5;
+        surveys[1].comment = Some("Big Meander".to_string());
+
+        let serialized = Survey::serialize_dat_file(&surveys);
+        let changed_lines: Vec<(&str, &str)> = input
+            .lines()
+            .zip(serialized.lines())
+            .filter(|(original, serialized)| original != serialized)
+            .collect();
+        assert_eq!(
+            changed_lines,
+            vec![
+                (
+                    "          A1           A2    21.75    63.50   -28.00     2.60     2.60     2.60     2.60",
+                    "          A1           A2    21.50    63.50   -28.00     2.60     2.60     2.60     2.60"
+                ),
+                (
+                    "SURVEY DATE: 6 20 1987  COMMENT:Big Meander Area",
+                    "SURVEY DATE: 6 20 1987  COMMENT:Big Meander"
+                ),
+            ]
+        );
+        assert_eq!(Survey::parse_dat_file(&serialized).unwrap(), surveys);
+    }
+
+    #[test]
+    fn lossless_round_trip_with_inserted_and_removed_shots() {
+        let input = include_str!("../../test_data/Fulford.dat");
+        let mut surveys = Survey::parse_dat_file(input).unwrap();
+        let shots = &mut surveys[0].shots;
+        assert_eq!((shots[7].from.as_str(), shots[7].to.as_str()), ("A8", "A9"));
+        shots.remove(7);
+        shots.insert(2, Shot::new("A3", "A3A", 5.0, 90.0, 0.0));
+
+        let serialized = Survey::serialize_dat_file(&surveys);
+        let mut expected: Vec<&str> = input
+            .lines()
+            .filter(|line| !line.starts_with("          A8           A9"))
+            .collect();
+        let after_a2 = expected
+            .iter()
+            .position(|line| line.starts_with("          A2           A3"))
+            .unwrap();
+        let inserted = serialized.lines().find(|line| line.contains("A3A")).unwrap();
+        expected.insert(after_a2 + 1, inserted);
+        assert_eq!(serialized.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(Survey::parse_dat_file(&serialized).unwrap(), surveys);
+    }
+
+    #[test]
+    fn write_dat_file() {
+        let input = include_str!("../../test_data/Fulford.dat");
+        let surveys = Survey::parse_dat_file(input).unwrap();
+
+        let mut written = Vec::new();
+        Survey::write_dat_file(&surveys, &mut written, LineEnding::CrLf).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert_str_eq!(written, Survey::format_dat_file(&surveys, LineEnding::CrLf));
+        assert_eq!(
+            written.matches('\n').count(),
+            written.matches("\r\n").count()
+        );
+        assert_eq!(written.matches("\x0c\r\n").count(), surveys.len());
+        assert!(written.ends_with("\x0c\r\n"));
+        assert_eq!(Survey::parse_dat_file(&written).unwrap(), surveys);
+
+        let written = Survey::format_dat_file(&surveys, LineEnding::Lf);
+        assert!(!written.contains('\r'));
+        assert_eq!(Survey::parse_dat_file(&written).unwrap(), surveys);
+    }
 }
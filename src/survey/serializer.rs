@@ -0,0 +1,231 @@
+use std::fmt::Write as _;
+
+use super::{parser, Shot, Survey};
+
+const COLUMN_LABELS: &str = "        FROM           TO   LENGTH  BEARING      INC     LEFT       UP     DOWN    RIGHT   FLAGS  COMMENTS";
+const COLUMN_LABELS_WITH_BACKSIGHTS: &str = "        FROM           TO   LENGTH  BEARING      INC     LEFT       UP     DOWN    RIGHT     AZM2     INC2   FLAGS  COMMENTS";
+
+/// The exact text of each part of a parsed survey, line endings included
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SurveySource {
+    pub(crate) cave_name: String,
+    pub(crate) name: String,
+    pub(crate) date: String,
+    pub(crate) team: String,
+    pub(crate) parameters: String,
+    /// Everything between the parameters and the first shot, including the column labels
+    pub(crate) column_header: String,
+    pub(crate) shots: Vec<String>,
+    /// Everything after the last shot, including the form feed that ends the survey
+    pub(crate) trailer: String,
+}
+
+impl SurveySource {
+    fn line_ending(&self) -> &'static str {
+        if self.cave_name.ends_with("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+fn cave_name_line(survey: &Survey, line_ending: &str) -> String {
+    format!("{}{line_ending}", survey.cave_name)
+}
+
+fn name_line(survey: &Survey, line_ending: &str) -> String {
+    format!("SURVEY NAME: {}{line_ending}", survey.name)
+}
+
+fn date_line(survey: &Survey, line_ending: &str) -> String {
+    let mut result = format!(
+        "SURVEY DATE: {} {} {}",
+        survey.date.month, survey.date.day, survey.date.year
+    );
+    if let Some(comment) = &survey.comment {
+        let _ = write!(result, "  COMMENT:{comment}");
+    }
+    result.push_str(line_ending);
+    result
+}
+
+fn team_lines(survey: &Survey, line_ending: &str) -> String {
+    format!("SURVEY TEAM: {line_ending}{}{line_ending}", survey.team)
+}
+
+fn parameters_line(survey: &Survey, line_ending: &str) -> String {
+    format!("{}{line_ending}", survey.parameters.serialize())
+}
+
+fn column_header(redundant_backsights: bool, line_ending: &str) -> String {
+    let labels = if redundant_backsights {
+        COLUMN_LABELS_WITH_BACKSIGHTS
+    } else {
+        COLUMN_LABELS
+    };
+    format!("{line_ending}{labels}{line_ending}{line_ending}")
+}
+
+fn shot_line(shot: &Shot, redundant_backsights: bool, line_ending: &str) -> String {
+    let mut result = format!(
+        "{:>12}{:>13}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}",
+        shot.from,
+        shot.to,
+        shot.length,
+        shot.azimuth.to_compass(),
+        shot.inclination.to_compass(),
+        shot.left.to_compass(),
+        shot.up.to_compass(),
+        shot.down.to_compass(),
+        shot.right.to_compass()
+    );
+    if redundant_backsights {
+        let _ = write!(
+            result,
+            "{:>9.2}{:>9.2}",
+            shot.backsight_azimuth.to_compass(),
+            shot.backsight_inclination.to_compass()
+        );
+    }
+    if let Some(flags) = &shot.flags {
+        let _ = write!(result, "  {}", flags.serialize());
+    }
+    if let Some(comment) = &shot.comment {
+        let _ = write!(result, "  {comment}");
+    }
+    result.push_str(line_ending);
+    result
+}
+
+fn trailer(line_ending: &str) -> String {
+    format!("\x0c{line_ending}")
+}
+
+pub(crate) fn serialize_survey(survey: &Survey, line_ending: &str) -> String {
+    let redundant_backsights = survey.parameters.has_redundant_backsights();
+    let mut result = String::new();
+    result.push_str(&cave_name_line(survey, line_ending));
+    result.push_str(&name_line(survey, line_ending));
+    result.push_str(&date_line(survey, line_ending));
+    result.push_str(&team_lines(survey, line_ending));
+    result.push_str(&parameters_line(survey, line_ending));
+    result.push_str(&column_header(redundant_backsights, line_ending));
+    for shot in &survey.shots {
+        result.push_str(&shot_line(shot, redundant_backsights, line_ending));
+    }
+    result.push_str(&trailer(line_ending));
+    result
+}
+
+/// Use the original text if it still parses to the current value, otherwise format the value fresh
+fn original_or<T: PartialEq>(
+    original: &str,
+    parse: impl Fn(&str) -> nom::IResult<&str, T>,
+    current: &T,
+    fresh: impl FnOnce() -> String,
+) -> String {
+    match parse(original) {
+        Ok((_, parsed)) if parsed == *current => original.to_string(),
+        _ => fresh(),
+    }
+}
+
+/// Pair each shot with a source line which still parses to it, keeping the longest run of shots in order
+/// Matching by content rather than position means inserting or removing a shot leaves the other lines alone.
+fn matching_source_lines<'a>(
+    shots: &[Shot],
+    source: &'a [String],
+    redundant_backsights: bool,
+) -> Vec<Option<&'a str>> {
+    let parsed: Vec<Option<Shot>> = source
+        .iter()
+        .map(|line| {
+            parser::parse_shot(line, redundant_backsights)
+                .ok()
+                .map(|(_, shot)| shot)
+        })
+        .collect();
+    let matches = |shot: usize, line: usize| parsed[line].as_ref() == Some(&shots[shot]);
+    // common[shot][line] is how many shots from `shot` on can be paired with lines from `line` on
+    let mut common = vec![vec![0_usize; parsed.len() + 1]; shots.len() + 1];
+    for shot in (0..shots.len()).rev() {
+        for line in (0..parsed.len()).rev() {
+            common[shot][line] = if matches(shot, line) {
+                common[shot + 1][line + 1] + 1
+            } else {
+                common[shot + 1][line].max(common[shot][line + 1])
+            };
+        }
+    }
+    let mut paired = vec![None; shots.len()];
+    let (mut shot, mut line) = (0, 0);
+    while shot < shots.len() && line < parsed.len() {
+        if matches(shot, line) {
+            paired[shot] = Some(source[line].as_str());
+            shot += 1;
+            line += 1;
+        } else if common[shot + 1][line] >= common[shot][line + 1] {
+            shot += 1;
+        } else {
+            line += 1;
+        }
+    }
+    paired
+}
+
+pub(crate) fn serialize_survey_lossless(survey: &Survey) -> String {
+    let Some(source) = &survey.source else {
+        return survey.serialize();
+    };
+    let line_ending = source.line_ending();
+    let redundant_backsights = survey.parameters.has_redundant_backsights();
+    let mut result = String::new();
+    result.push_str(&original_or(
+        &source.cave_name,
+        parser::parse_cave_name,
+        &survey.cave_name,
+        || cave_name_line(survey, line_ending),
+    ));
+    result.push_str(&original_or(
+        &source.name,
+        parser::parse_survey_name,
+        &survey.name,
+        || name_line(survey, line_ending),
+    ));
+    result.push_str(&original_or(
+        &source.date,
+        parser::parse_survey_date_line,
+        &(survey.date, survey.comment.clone()),
+        || date_line(survey, line_ending),
+    ));
+    result.push_str(&original_or(
+        &source.team,
+        parser::parse_survey_team,
+        &survey.team,
+        || team_lines(survey, line_ending),
+    ));
+    result.push_str(&original_or(
+        &source.parameters,
+        parser::parse_survey_parameters,
+        &survey.parameters,
+        || parameters_line(survey, line_ending),
+    ));
+    // The column labels are ignored by Compass, so they only need rewriting if the backsight columns changed
+    let original_backsights = parser::parse_survey_parameters(&source.parameters)
+        .is_ok_and(|(_, parameters)| parameters.has_redundant_backsights());
+    if original_backsights == redundant_backsights {
+        result.push_str(&source.column_header);
+    } else {
+        result.push_str(&column_header(redundant_backsights, line_ending));
+    }
+    let original_lines = matching_source_lines(&survey.shots, &source.shots, redundant_backsights);
+    for (shot, original) in survey.shots.iter().zip(original_lines) {
+        match original {
+            Some(original) => result.push_str(original),
+            None => result.push_str(&shot_line(shot, redundant_backsights, line_ending)),
+        }
+    }
+    result.push_str(&source.trailer);
+    result
+}
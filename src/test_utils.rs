@@ -0,0 +1,25 @@
+//! Fixtures shared by the unit tests
+
+use crate::{Date, Parameters, Shot, Survey};
+
+/// A survey of the given shots, with no declination, correction factors or format
+pub(crate) fn survey(name: &str, shots: Vec<Shot>) -> Survey {
+    let mut survey = Survey::new(
+        "Test Cave",
+        name,
+        Date {
+            month: 1,
+            day: 2,
+            year: 2003,
+        },
+        "",
+        Parameters {
+            declination: 0.0,
+            format: None,
+            correction_factors: None,
+            backsight_correction_factors: None,
+        },
+    );
+    survey.shots = shots;
+    survey
+}
@@ -0,0 +1,7 @@
+@357715.717,4372837.574,3048.000,13,-1.050;
+&North American 1983;
+$12;
+/
+#Fulford.dat,
+  A1[M,873711.528,4380552.980,3048.000];
+#Fulsurf.dat;